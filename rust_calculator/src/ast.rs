@@ -0,0 +1,391 @@
+use std::fmt;
+
+use crate::lexer::Span;
+use crate::units::Unit;
+
+/// A parsed expression. Every node remembers the span of input it was parsed
+/// from, so evaluation errors can point back at the source.
+///
+/// `Display` prints the expression back in a normalized form, with single
+/// spaces around operators and only the parentheses that are needed. Numbers
+/// are printed as they were written, so no digits are lost:
+///
+/// ```
+/// let expr = rust_calculator::parse("((1+2))*3").unwrap();
+/// assert_eq!(expr.to_string(), "(1 + 2) * 3");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number {
+        value: f64,
+        /// The literal as written, which modes other than `f64` read exactly.
+        text: String,
+        span: Span,
+    },
+    /// A number literal followed by `i`, such as `4i`.
+    Imaginary {
+        value: f64,
+        /// The literal as written, including the `i`.
+        text: String,
+        span: Span,
+    },
+    Variable {
+        name: String,
+        span: Span,
+    },
+    /// `$n`, the n-th result of the session, counting from 1.
+    ResultRef {
+        index: usize,
+        span: Span,
+    },
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+        span: Span,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+        span: Span,
+    },
+    Call {
+        name: String,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    /// A value with a unit written after it, such as `5 km` or `9.81 m/s^2`.
+    Quantity {
+        value: Box<Expr>,
+        unit: Unit,
+        span: Span,
+    },
+    /// `value to unit` or `value in unit`, converting a quantity.
+    Convert {
+        value: Box<Expr>,
+        unit: Unit,
+        span: Span,
+    },
+}
+
+/// One line of input: an expression to evaluate, an assignment that stores
+/// its value under a name, or a function definition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Expr(Expr),
+    /// `let name = value` or `name = value`.
+    Assign {
+        name: String,
+        name_span: Span,
+        value: Expr,
+    },
+    /// `name(param, ...) = body`, optionally preceded by `let`.
+    Define {
+        name: String,
+        name_span: Span,
+        params: Vec<(String, Span)>,
+        body: Expr,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Plus,
+    /// Postfix `n!`, the product of `1..=n`.
+    Factorial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    /// `a // b`: division rounded towards negative infinity, so `-7 // 2` is `-4`.
+    FloorDiv,
+    /// `a % b`: remainder of truncating division. It takes the sign of `a`,
+    /// so `-7 % 3` is `-1` and `7 % -3` is `1`.
+    Rem,
+    /// `a mod b`: Euclidean modulo, always in `0..|b|`, so `-7 mod 3` is `2`
+    /// and `-7 mod -3` is also `2`.
+    Mod,
+    Pow,
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Number { span, .. }
+            | Expr::Imaginary { span, .. }
+            | Expr::Variable { span, .. }
+            | Expr::ResultRef { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Binary { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Quantity { span, .. }
+            | Expr::Convert { span, .. } => *span,
+        }
+    }
+
+    /// Replaces this node's span, e.g. to include surrounding parentheses.
+    pub(crate) fn with_span(mut self, new_span: Span) -> Expr {
+        match &mut self {
+            Expr::Number { span, .. }
+            | Expr::Imaginary { span, .. }
+            | Expr::Variable { span, .. }
+            | Expr::ResultRef { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Binary { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Quantity { span, .. }
+            | Expr::Convert { span, .. } => *span = new_span,
+        }
+        self
+    }
+
+    /// How tightly this expression binds when printed; atoms bind tightest.
+    /// A unit takes a following `^` as its own, so `(5 m)^2` needs its
+    /// parentheses, and conversions bind loosest of all.
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary { op, .. } => op.precedence(),
+            Expr::Unary { op, .. } => op.precedence(),
+            Expr::Quantity { .. } => BinaryOp::Pow.precedence(),
+            Expr::Convert { .. } => 0,
+            _ => 5,
+        }
+    }
+}
+
+impl UnaryOp {
+    /// Prefix signs bind tighter than `*` but looser than `^`, so `-2^2` is `-(2^2)`.
+    pub const PRECEDENCE: u8 = 3;
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Plus => "+",
+            UnaryOp::Factorial => "!",
+        }
+    }
+
+    /// Whether the operator is written after its operand.
+    pub fn is_postfix(self) -> bool {
+        self == UnaryOp::Factorial
+    }
+
+    /// `!` binds tightest of all, so `2^3!` is `2^(3!)` and `-3!` is `-(3!)`.
+    pub fn precedence(self) -> u8 {
+        match self {
+            UnaryOp::Neg | UnaryOp::Plus => UnaryOp::PRECEDENCE,
+            UnaryOp::Factorial => 5,
+        }
+    }
+}
+
+impl BinaryOp {
+    pub fn from_symbol(symbol: &str) -> Option<BinaryOp> {
+        match symbol {
+            "+" => Some(BinaryOp::Add),
+            "-" => Some(BinaryOp::Sub),
+            "*" => Some(BinaryOp::Mul),
+            "/" => Some(BinaryOp::Div),
+            "//" => Some(BinaryOp::FloorDiv),
+            "%" => Some(BinaryOp::Rem),
+            "mod" => Some(BinaryOp::Mod),
+            "^" | "**" => Some(BinaryOp::Pow),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::FloorDiv => "//",
+            BinaryOp::Rem => "%",
+            BinaryOp::Mod => "mod",
+            BinaryOp::Pow => "^",
+        }
+    }
+
+    /// Higher binds tighter: `^` before the multiplicative operators
+    /// (`*`, `/`, `//`, `%`, `mod`), which come before `+` and `-`.
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Add | BinaryOp::Sub => 1,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::FloorDiv | BinaryOp::Rem | BinaryOp::Mod => 2,
+            BinaryOp::Pow => 4,
+        }
+    }
+
+    /// `^` groups from the right, so `2^3^2` is `2^(3^2)`; everything else from the left.
+    pub fn is_right_associative(self) -> bool {
+        self == BinaryOp::Pow
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Number { text, .. } | Expr::Imaginary { text, .. } => write!(f, "{}", text),
+            Expr::Variable { name, .. } => write!(f, "{}", name),
+            Expr::ResultRef { index, .. } => write!(f, "${}", index),
+            Expr::Unary { op, operand, .. } if op.is_postfix() => {
+                write_operand(f, operand, operand.precedence() < self.precedence())?;
+                write!(f, "{}", op.symbol())
+            }
+            Expr::Unary { op, operand, .. } => {
+                write!(f, "{}", op.symbol())?;
+                write_operand(f, operand, operand.precedence() < self.precedence())
+            }
+            Expr::Binary {
+                op, left, right, ..
+            } => {
+                // An equally tight operand on the side the operator does not
+                // group from needs parentheses: `8 - (3 - 2)` and `(2 ^ 3) ^ 2`
+                let (left_parens, right_parens) = if op.is_right_associative() {
+                    (
+                        left.precedence() <= op.precedence(),
+                        right.precedence() < op.precedence(),
+                    )
+                } else {
+                    (
+                        left.precedence() < op.precedence(),
+                        right.precedence() <= op.precedence(),
+                    )
+                };
+                write_operand(f, left, left_parens)?;
+                write!(f, " {} ", op.symbol())?;
+                write_operand(f, right, right_parens)
+            }
+            Expr::Call { name, args, .. } => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Quantity { value, unit, .. } => {
+                write_operand(f, value, value.precedence() < 5)?;
+                write!(f, " {}", unit)
+            }
+            Expr::Convert { value, unit, .. } => write!(f, "{} to {}", value, unit),
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Statement::Expr(expr) => write!(f, "{}", expr),
+            Statement::Assign { name, value, .. } => write!(f, "{} = {}", name, value),
+            Statement::Define {
+                name, params, body, ..
+            } => {
+                let params: Vec<&str> = params.iter().map(|(param, _)| param.as_str()).collect();
+                write!(f, "{}({}) = {}", name, params.join(", "), body)
+            }
+        }
+    }
+}
+
+fn write_operand(f: &mut fmt::Formatter, expr: &Expr, parenthesize: bool) -> fmt::Result {
+    if parenthesize {
+        write!(f, "({})", expr)
+    } else {
+        write!(f, "{}", expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse, parse_statement};
+
+    fn normalize(input: &str) -> String {
+        parse(input).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_display_keeps_needed_parentheses() {
+        assert_eq!(normalize("(1 + 2) * 3"), "(1 + 2) * 3");
+        assert_eq!(normalize("8 - (3 - 2)"), "8 - (3 - 2)");
+        assert_eq!(normalize("8 / (4 * 2)"), "8 / (4 * 2)");
+    }
+
+    #[test]
+    fn test_display_drops_redundant_parentheses() {
+        assert_eq!(normalize("((7))"), "7");
+        assert_eq!(normalize("(2*3)+4"), "2 * 3 + 4");
+        assert_eq!(normalize("(8 - 3) - 2"), "8 - 3 - 2");
+    }
+
+    #[test]
+    fn test_display_calls_and_signs() {
+        assert_eq!(normalize("max(1,2+3)"), "max(1, 2 + 3)");
+        assert_eq!(normalize("-5 + x"), "-5 + x");
+        assert_eq!(normalize("-(3)"), "-3");
+        assert_eq!(normalize("-(1 + 2)"), "-(1 + 2)");
+        assert_eq!(normalize("$2*ans"), "$2 * ans");
+    }
+
+    #[test]
+    fn test_display_keeps_numbers_as_written() {
+        assert_eq!(normalize("1.50+2e3"), "1.50 + 2e3");
+        assert_eq!(normalize("3-4.0i*i"), "3 - 4.0i * i");
+        assert_eq!(
+            normalize("123456789012345678901234567890"),
+            "123456789012345678901234567890"
+        );
+    }
+
+    #[test]
+    fn test_display_assignment() {
+        let statement = parse_statement("let  rate=(0.07)").unwrap();
+        assert_eq!(statement.to_string(), "rate = 0.07");
+    }
+
+    #[test]
+    fn test_display_definition() {
+        let statement = parse_statement("f( x,y )=x^2+y").unwrap();
+        assert_eq!(statement.to_string(), "f(x, y) = x ^ 2 + y");
+    }
+
+    #[test]
+    fn test_display_powers() {
+        assert_eq!(normalize("2**3**2"), "2 ^ 3 ^ 2");
+        assert_eq!(normalize("(2^3)^2"), "(2 ^ 3) ^ 2");
+        assert_eq!(normalize("-2^2"), "-2 ^ 2");
+        assert_eq!(normalize("(-2)^2"), "(-2) ^ 2");
+    }
+
+    #[test]
+    fn test_display_factorials() {
+        assert_eq!(normalize("3 !!"), "3!!");
+        assert_eq!(normalize("(1+2)! * -3!"), "(1 + 2)! * -3!");
+        assert_eq!(normalize("(-3)!"), "(-3)!");
+        assert_eq!(normalize("2^3!"), "2 ^ 3!");
+        assert_eq!(normalize("(2^3)!"), "(2 ^ 3)!");
+    }
+
+    #[test]
+    fn test_display_units() {
+        assert_eq!(normalize("5km+300 m"), "5 km + 300 m");
+        assert_eq!(normalize("9.81 m/s ^ 2"), "9.81 m/s^2");
+        assert_eq!(normalize("(5 m)^2 in ft^2"), "(5 m) ^ 2 to ft^2");
+        assert_eq!(normalize("(1 + 2 h) * 3 s^-1"), "(1 + 2 h) * 3 s^-1");
+        assert_eq!(normalize("-(60 mph to m/s)"), "-(60 mph to m/s)");
+    }
+
+    #[test]
+    fn test_display_remainders() {
+        assert_eq!(normalize("7  mod  3"), "7 mod 3");
+        assert_eq!(normalize("7 // (2 % 3)"), "7 // (2 % 3)");
+    }
+}
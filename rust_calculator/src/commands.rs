@@ -0,0 +1,967 @@
+use std::fs;
+
+use num_traits::Signed;
+
+use crate::complex::ComplexStyle;
+use crate::constants::CONSTANTS;
+use crate::decimal::{self, Rounding};
+use crate::error::closest_match;
+use crate::functions::FUNCTIONS;
+use crate::integer;
+use crate::rational::FractionStyle;
+use crate::units::{PREFIXES, UNITS};
+use crate::{eval_with, parse, script, Calculator, Mode, Number};
+
+/// A REPL command such as `:vars`. Programs embedding the calculator can add
+/// their own to a [`Commands`] table:
+///
+/// ```
+/// use rust_calculator::commands::{Action, Command, Commands};
+/// use rust_calculator::Calculator;
+///
+/// let mut commands = Commands::new();
+/// commands.add(Command {
+///     name: ":count",
+///     aliases: &[],
+///     usage: ":count",
+///     description: "show how many results there are",
+///     run: |calculator, _, _| Ok(Action::Print(calculator.results().len().to_string())),
+/// });
+///
+/// let mut calculator = Calculator::new();
+/// calculator.evaluate("1 + 1").unwrap();
+/// let action = commands.run(&mut calculator, ":count");
+/// assert_eq!(action, Some(Ok(Action::Print("1".to_string()))));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Command {
+    /// What the user types, including the colon.
+    pub name: &'static str,
+    /// Other spellings. Aliases without a colon, like `quit`, only match when
+    /// they are the whole line, so they never hide a calculation.
+    pub aliases: &'static [&'static str],
+    /// The name with its arguments, as `:help` shows it.
+    pub usage: &'static str,
+    /// One-line summary shown by `:help`.
+    pub description: &'static str,
+    /// Runs the command with the rest of the line as `args`. Errors are
+    /// messages to show the user.
+    pub run: fn(&mut Calculator, &Commands, &str) -> Result<Action, String>,
+}
+
+/// What the REPL should do once a command has run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Show the text, which may be empty, and read the next line.
+    Print(String),
+    /// Clear the terminal.
+    ClearScreen,
+    /// End the session.
+    Quit,
+}
+
+/// The table of commands the REPL recognizes.
+#[derive(Debug, Clone)]
+pub struct Commands {
+    commands: Vec<Command>,
+}
+
+impl Default for Commands {
+    fn default() -> Commands {
+        Commands {
+            commands: BUILT_IN.to_vec(),
+        }
+    }
+}
+
+impl Commands {
+    /// The built-in commands.
+    pub fn new() -> Commands {
+        Commands::default()
+    }
+
+    /// Adds a command, replacing any existing command with the same name.
+    pub fn add(&mut self, command: Command) {
+        self.commands
+            .retain(|existing| existing.name != command.name);
+        self.commands.push(command);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Command> {
+        self.commands.iter()
+    }
+
+    /// Every name and alias, for completion.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.commands.iter().flat_map(|command| {
+            std::iter::once(command.name).chain(command.aliases.iter().copied())
+        })
+    }
+
+    /// Runs `line` if it is a command. Returns `None` for anything else,
+    /// which should be evaluated as a calculation instead.
+    pub fn run(&self, calculator: &mut Calculator, line: &str) -> Option<Result<Action, String>> {
+        let line = line.trim();
+        let (word, args) = match line.split_once(char::is_whitespace) {
+            Some((word, args)) => (word, args.trim()),
+            None => (line, ""),
+        };
+        let word = word.to_lowercase();
+        let found = self.commands.iter().find(|command| {
+            command.name == word
+                || command
+                    .aliases
+                    .iter()
+                    .any(|alias| *alias == word && (alias.starts_with(':') || args.is_empty()))
+        });
+        match found {
+            Some(command) => Some((command.run)(calculator, self, args)),
+            None if word.starts_with(':') => {
+                let mut message = format!("Unknown command '{}'", word);
+                if let Some(suggestion) = closest_match(&word, self.names()) {
+                    message.push_str(&format!(", did you mean '{}'?", suggestion));
+                }
+                message.push_str(" Type :help to see every command.");
+                Some(Err(message))
+            }
+            None => None,
+        }
+    }
+
+    /// The text of `:help`: the commands, operators and functions.
+    pub fn help(&self) -> String {
+        let mut help = String::from("Commands:\n");
+        for command in &self.commands {
+            let mut description = command.description.to_string();
+            if !command.aliases.is_empty() {
+                description.push_str(&format!(" (also {})", command.aliases.join(", ")));
+            }
+            help.push_str(&format!("  {:<18} {}\n", command.usage, description));
+        }
+        help.push_str(OPERATOR_HELP);
+        help.push_str("\nFunctions:\n");
+        for function in FUNCTIONS {
+            help.push_str(&format!(
+                "  {:<18} {}\n",
+                function.name, function.description
+            ));
+        }
+        help.push_str(&format!(
+            "  {:<18} {}",
+            "if", "if(condition, then, otherwise), evaluating only one branch"
+        ));
+        help
+    }
+}
+
+const OPERATOR_HELP: &str = "
+Operators, loosest first:
+  x to km, x in km   convert to another unit
+  + -                add, subtract
+  * / // % mod       multiply, divide, floor divide, remainder, modulo
+  -x +x              signs
+  ^ **               power, grouping from the right
+  n!                 factorial
+  ( )                grouping
+
+Other input:
+  x = 2, let x = 2   assign a variable
+  f(x, y) = x * y    define a function
+  ans, _, $n         the last result and the n-th result
+  3+4i, i            a complex number and the imaginary unit
+  5 km, 9.81 m/s^2   a number with a unit; x * 1 km for a variable
+";
+
+const BUILT_IN: &[Command] = &[
+    Command {
+        name: ":help",
+        aliases: &[":h", ":?"],
+        usage: ":help",
+        description: "show this help",
+        run: |_, commands, _| Ok(Action::Print(commands.help())),
+    },
+    Command {
+        name: ":vars",
+        aliases: &[],
+        usage: ":vars",
+        description: "list the variables",
+        run: |calculator, _, _| {
+            let env = calculator.environment();
+            let variables: Vec<String> = env
+                .variables()
+                .map(|(name, value)| format!("  {} = {}", name, env.format(value)))
+                .collect();
+            if variables.is_empty() {
+                return Ok(Action::Print(
+                    "No variables defined yet. Try: let rate = 0.07".into(),
+                ));
+            }
+            Ok(Action::Print(variables.join("\n")))
+        },
+    },
+    Command {
+        name: ":funcs",
+        aliases: &[],
+        usage: ":funcs",
+        description: "list the functions you have defined",
+        run: |calculator, _, _| {
+            let functions: Vec<String> = calculator
+                .environment()
+                .functions()
+                .map(|function| format!("  {}", function))
+                .collect();
+            if functions.is_empty() {
+                return Ok(Action::Print(
+                    "No functions defined yet. Try: f(x, y) = x^2 + y".into(),
+                ));
+            }
+            Ok(Action::Print(functions.join("\n")))
+        },
+    },
+    Command {
+        name: ":constants",
+        aliases: &[],
+        usage: ":constants",
+        description: "list the named constants and their units",
+        run: |calculator, _, _| Ok(Action::Print(constants_table(calculator))),
+    },
+    Command {
+        name: ":units",
+        aliases: &[],
+        usage: ":units",
+        description: "list the units and SI prefixes",
+        run: |_, _, _| Ok(Action::Print(units_table())),
+    },
+    Command {
+        name: ":history",
+        aliases: &[],
+        usage: ":history",
+        description: "list every result with the $n that refers to it",
+        run: |calculator, _, _| {
+            let env = calculator.environment();
+            let history: Vec<String> = calculator
+                .history()
+                .map(|(number, input, result)| {
+                    format!("  ${:<3} {} = {}", number, input, env.format(result))
+                })
+                .collect();
+            if history.is_empty() {
+                return Ok(Action::Print("No results yet.".into()));
+            }
+            Ok(Action::Print(history.join("\n")))
+        },
+    },
+    Command {
+        name: ":factor",
+        aliases: &[],
+        usage: ":factor <integer>",
+        description: "show the prime factors of an integer, e.g. :factor 360",
+        run: |calculator, _, args| {
+            if args.is_empty() {
+                return Err("Usage: :factor <integer>".into());
+            }
+            let value = parse(args)
+                .and_then(|expr| eval_with(&expr, calculator.environment()))
+                .map_err(|error| error.diagnostic().render(args))?;
+            Ok(Action::Print(factorization(&value)?))
+        },
+    },
+    Command {
+        name: ":physics",
+        aliases: &[],
+        usage: ":physics on|off",
+        description: "turn the physical constants on or off",
+        run: |calculator, _, args| {
+            match args {
+                "on" => calculator.environment_mut().set_physics(true),
+                "off" => calculator.environment_mut().set_physics(false),
+                _ => return Err("Usage: :physics on|off".into()),
+            }
+            Ok(Action::Print(format!("Physical constants are {}", args)))
+        },
+    },
+    Command {
+        name: ":mode",
+        aliases: &[],
+        usage: ":mode [float|decimal|rational|int|complex]",
+        description: "show or change the kind of numbers to calculate with",
+        run: |calculator, _, args| {
+            if !args.is_empty() {
+                let mode = args
+                    .parse::<Mode>()
+                    .map_err(|message| capitalize(&message))?;
+                calculator.environment_mut().set_mode(mode);
+            }
+            Ok(Action::Print(mode_summary(calculator)))
+        },
+    },
+    Command {
+        name: ":precision",
+        aliases: &[],
+        usage: ":precision [digits]",
+        description: "show or change the significant digits of decimal mode",
+        run: |calculator, _, args| {
+            if !args.is_empty() {
+                let digits = args
+                    .parse()
+                    .map_err(|_| "Usage: :precision [digits]".to_string())?;
+                let env = calculator.environment_mut();
+                let settings = env
+                    .decimal()
+                    .with_precision(digits)
+                    .map_err(|message| capitalize(&message))?;
+                env.set_decimal(settings);
+            }
+            Ok(Action::Print(mode_summary(calculator)))
+        },
+    },
+    Command {
+        name: ":rounding",
+        aliases: &[],
+        usage: ":rounding [mode]",
+        description: "show or change how decimal mode rounds, e.g. half-up",
+        run: |calculator, _, args| {
+            if !args.is_empty() {
+                let rounding = args
+                    .parse::<Rounding>()
+                    .map_err(|message| capitalize(&message))?;
+                let env = calculator.environment_mut();
+                env.set_decimal(env.decimal().with_rounding(rounding));
+            }
+            Ok(Action::Print(mode_summary(calculator)))
+        },
+    },
+    Command {
+        name: ":fractions",
+        aliases: &[],
+        usage: ":fractions [style]",
+        description: "show fractions as improper (3/2), mixed (1 1/2) or decimal",
+        run: |calculator, _, args| {
+            if !args.is_empty() {
+                let style = args
+                    .parse::<FractionStyle>()
+                    .map_err(|message| capitalize(&message))?;
+                calculator.environment_mut().set_fractions(style);
+            }
+            Ok(Action::Print(mode_summary(calculator)))
+        },
+    },
+    Command {
+        name: ":complex",
+        aliases: &[],
+        usage: ":complex [style]",
+        description: "show complex numbers as rectangular (3+4i) or polar (5 * e^(0.93i))",
+        run: |calculator, _, args| {
+            if !args.is_empty() {
+                let style = args
+                    .parse::<ComplexStyle>()
+                    .map_err(|message| capitalize(&message))?;
+                calculator.environment_mut().set_complex(style);
+            }
+            Ok(Action::Print(format!(
+                "Complex numbers are shown in {} form",
+                calculator.environment().complex()
+            )))
+        },
+    },
+    Command {
+        name: ":reset",
+        aliases: &[],
+        usage: ":reset",
+        description: "forget every variable, function and result",
+        run: |calculator, _, _| {
+            calculator.reset();
+            Ok(Action::Print("Session reset.".into()))
+        },
+    },
+    Command {
+        name: ":clear",
+        aliases: &[],
+        usage: ":clear",
+        description: "clear the screen",
+        run: |_, _, _| Ok(Action::ClearScreen),
+    },
+    Command {
+        name: ":save",
+        aliases: &[],
+        usage: ":save <file>",
+        description: "save the variables and functions as a script",
+        run: |calculator, _, path| {
+            if path.is_empty() {
+                return Err("Usage: :save <file>".into());
+            }
+            fs::write(path, session_script(calculator))
+                .map_err(|error| format!("Could not write {}: {}", path, error))?;
+            Ok(Action::Print(format!("Saved to {}", path)))
+        },
+    },
+    Command {
+        name: ":load",
+        aliases: &[],
+        usage: ":load <file>",
+        description: "run a script, e.g. one written by :save",
+        run: |calculator, commands, path| {
+            if path.is_empty() {
+                return Err("Usage: :load <file>".into());
+            }
+            let source = fs::read_to_string(path)
+                .map_err(|error| format!("Could not read {}: {}", path, error))?;
+            let chunks = script::split(&source);
+            // Statements before an error stay loaded, as in a script run
+            for chunk in &chunks {
+                // Settings such as the `:mode` lines that `:save` writes
+                if chunk.text.trim_start().starts_with(':') {
+                    if let Some(Err(message)) = commands.run(calculator, &chunk.text) {
+                        return Err(format!("{}:{}: {}", path, chunk.line, message));
+                    }
+                    continue;
+                }
+                if let Err(error) = calculator.evaluate(&chunk.text) {
+                    let mut diagnostic = error.diagnostic();
+                    diagnostic.span = chunk.locate(diagnostic.span);
+                    return Err(diagnostic.render_in_file(path, &source));
+                }
+            }
+            let plural = if chunks.len() == 1 { "" } else { "s" };
+            Ok(Action::Print(format!(
+                "Loaded {} statement{} from {}",
+                chunks.len(),
+                plural,
+                path
+            )))
+        },
+    },
+    Command {
+        name: ":quit",
+        aliases: &[":q", ":exit", "quit", "exit"],
+        usage: ":quit",
+        description: "leave the calculator, like Ctrl-D",
+        run: |_, _, _| Ok(Action::Quit),
+    },
+];
+
+/// The current mode, with the settings that apply to it.
+fn mode_summary(calculator: &Calculator) -> String {
+    let env = calculator.environment();
+    match env.mode() {
+        Mode::Decimal => format!("Mode: decimal ({})", env.decimal()),
+        Mode::Rational => format!("Mode: rational ({} fractions)", env.fractions()),
+        Mode::Complex => format!("Mode: complex ({} form)", env.complex()),
+        mode => format!("Mode: {}", mode),
+    }
+}
+
+/// `value` as a product of primes, like `360 = 2^3 * 3^2 * 5`.
+fn factorization(value: &Number) -> Result<String, String> {
+    let n = value
+        .to_integer()
+        .ok_or("Only integers have prime factors")?;
+    let factors = integer::factor(&n).map_err(|message| capitalize(&message))?;
+    let mut terms: Vec<String> = factors
+        .iter()
+        .map(|(prime, count)| match count {
+            1 => prime.to_string(),
+            count => format!("{}^{}", prime, count),
+        })
+        .collect();
+    if n.is_negative() {
+        terms.insert(0, "-1".to_string());
+    }
+    Ok(format!("{} = {}", n, terms.join(" * ")))
+}
+
+/// Error messages from parsing settings start lowercase, as befits the
+/// command line; REPL messages are sentences.
+fn capitalize(message: &str) -> String {
+    let mut chars = message.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Lists every constant, marking the physical ones that are switched off.
+fn constants_table(calculator: &Calculator) -> String {
+    let physics = calculator.environment().physics_enabled();
+    let rows: Vec<String> = CONSTANTS
+        .iter()
+        .map(|constant| {
+            let unit = if constant.unit.is_empty() {
+                String::new()
+            } else {
+                format!(" {}", constant.unit)
+            };
+            let note = if constant.physics && !physics {
+                " (enable with :physics on)"
+            } else {
+                ""
+            };
+            format!(
+                "  {:<4} = {:<30} {}{}",
+                constant.name,
+                readable(constant.value) + &unit,
+                constant.description,
+                note
+            )
+        })
+        .collect();
+    rows.join("\n")
+}
+
+/// Lists every unit in SI base units, then the prefixes.
+fn units_table() -> String {
+    let mut rows: Vec<String> = UNITS
+        .iter()
+        .map(|unit| {
+            let dimension = unit.dimension.to_string();
+            let value = if dimension.is_empty() {
+                readable(unit.scale)
+            } else {
+                format!("{} {}", readable(unit.scale), dimension)
+            };
+            let note = if unit.prefixes {
+                ", takes prefixes"
+            } else {
+                ""
+            };
+            format!(
+                "  {:<4} = {:<30} {}{}",
+                unit.symbol, value, unit.description, note
+            )
+        })
+        .collect();
+    let mut prefixes = PREFIXES.to_vec();
+    prefixes.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    let prefixes: Vec<&str> = prefixes.iter().map(|(prefix, _)| *prefix).collect();
+    rows.push(format!("Prefixes: {}", prefixes.join(" ")));
+    rows.join("\n")
+}
+
+/// `value`, in scientific notation if it is very large or very small.
+fn readable(value: f64) -> String {
+    let magnitude = value.abs();
+    if magnitude != 0.0 && magnitude.is_finite() && !(1e-4..1e15).contains(&magnitude) {
+        format!("{:e}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// The variables and functions as statements that `:load` and `run` accept.
+///
+/// Literals read differently in each mode, so every variable is written
+/// under a `:mode` line that reads it back as the same kind of number, and
+/// decimals with enough precision to keep all their digits. The session's
+/// own mode, precision and rounding are restored at the end.
+fn session_script(calculator: &Calculator) -> String {
+    let env = calculator.environment();
+    let mut script = String::from("# Saved by rust_calculator\n");
+    for mode in [Mode::Float, Mode::Decimal, Mode::Rational] {
+        let mut variables = env
+            .variables()
+            .filter(|(_, value)| reading_mode(value) == mode)
+            .peekable();
+        if variables.peek().is_none() {
+            continue;
+        }
+        script.push_str(&format!(":mode {}\n", mode));
+        if mode == Mode::Decimal {
+            script.push_str(&format!(":precision {}\n", decimal::MAX_PRECISION));
+        }
+        for (name, value) in variables {
+            script.push_str(&format!("{} = {}\n", name, lossless(value)));
+        }
+    }
+    for function in env.functions() {
+        script.push_str(&format!("{}\n", function));
+    }
+    script.push_str(&format!(":mode {}\n", env.mode()));
+    script.push_str(&format!(":precision {}\n", env.decimal().precision()));
+    script.push_str(&format!(":rounding {}\n", env.decimal().rounding()));
+    script
+}
+
+/// The mode whose literals read back as `value`'s kind of number.
+fn reading_mode(value: &Number) -> Mode {
+    match value {
+        Number::Decimal(_) => Mode::Decimal,
+        Number::Rational(_) => Mode::Rational,
+        _ => Mode::Float,
+    }
+}
+
+/// `value` as a literal that reads back as exactly the same number.
+fn lossless(value: &Number) -> String {
+    match value {
+        Number::Float(value) => float_literal(*value),
+        // An imaginary literal like `3.0i`, unlike `3.0 * i`, still means
+        // the same after a variable called `i` is assigned
+        Number::Complex(value) if value.im.is_finite() => {
+            format!("{} + {:?}i", float_literal(value.re), value.im)
+        }
+        Number::Complex(value) => format!(
+            "{} + {} * 1i",
+            float_literal(value.re),
+            float_literal(value.im)
+        ),
+        Number::Quantity(quantity) => {
+            format!("{} {}", float_literal(quantity.value), quantity.unit)
+        }
+        value => value.to_string(),
+    }
+}
+
+/// A float that stays a float when read back: `3.0` rather than the integer
+/// `3`, and infinity and NaN spelled as the constants.
+fn float_literal(value: f64) -> String {
+    if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(calculator: &mut Calculator, line: &str) -> Option<Result<Action, String>> {
+        Commands::new().run(calculator, line)
+    }
+
+    fn print(calculator: &mut Calculator, line: &str) -> String {
+        match run(calculator, line) {
+            Some(Ok(Action::Print(text))) => text,
+            other => panic!("{} gave {:?}", line, other),
+        }
+    }
+
+    #[test]
+    fn test_calculations_are_not_commands() {
+        let mut calculator = Calculator::new();
+        assert_eq!(run(&mut calculator, "1 + 2"), None);
+        assert_eq!(run(&mut calculator, "quit = 3"), None);
+        assert_eq!(run(&mut calculator, "exit + 1"), None);
+    }
+
+    #[test]
+    fn test_quit_aliases() {
+        let mut calculator = Calculator::new();
+        for line in [":quit", ":q", "exit", "quit", "QUIT", "  :exit  "] {
+            assert_eq!(
+                run(&mut calculator, line),
+                Some(Ok(Action::Quit)),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_commands() {
+        let mut calculator = Calculator::new();
+        let Some(Err(message)) = run(&mut calculator, ":varz") else {
+            panic!("expected an error");
+        };
+        assert!(message.starts_with("Unknown command ':varz', did you mean ':vars'?"));
+        assert_eq!(
+            run(&mut calculator, ":physics maybe"),
+            Some(Err("Usage: :physics on|off".into()))
+        );
+    }
+
+    #[test]
+    fn test_listings_and_reset() {
+        let mut calculator = Calculator::new();
+        calculator.evaluate("rate = 0.5").unwrap();
+        calculator.evaluate("f(x) = x * rate").unwrap();
+        calculator.evaluate("f(4)").unwrap();
+        assert_eq!(print(&mut calculator, ":vars"), "  rate = 0.5");
+        assert_eq!(print(&mut calculator, ":funcs"), "  f(x) = x * rate");
+        assert!(print(&mut calculator, ":history").ends_with("$2   f(4) = 2"));
+        assert!(print(&mut calculator, ":help").contains(":save <file>"));
+
+        calculator.environment_mut().set_physics(true);
+        print(&mut calculator, ":reset");
+        assert!(print(&mut calculator, ":vars").starts_with("No variables"));
+        assert!(calculator.results().is_empty());
+        assert!(calculator.environment().physics_enabled());
+    }
+
+    #[test]
+    fn test_mode_settings() {
+        let mut calculator = Calculator::new();
+        assert_eq!(print(&mut calculator, ":mode"), "Mode: float");
+        assert_eq!(
+            print(&mut calculator, ":mode decimal"),
+            "Mode: decimal (28 digits, rounding half-even)"
+        );
+        print(&mut calculator, ":precision 3");
+        print(&mut calculator, ":rounding half-up");
+        assert_eq!(
+            calculator.evaluate("2 / 3").unwrap().unwrap().to_string(),
+            "0.667"
+        );
+        assert_eq!(
+            run(&mut calculator, ":mode exact"),
+            Some(Err(
+                "Unknown mode 'exact', expected one of float, decimal, rational, int, complex"
+                    .into()
+            ))
+        );
+        assert!(run(&mut calculator, ":precision lots").unwrap().is_err());
+        assert!(run(&mut calculator, ":rounding nearest").unwrap().is_err());
+
+        // Settings survive a reset
+        print(&mut calculator, ":reset");
+        assert_eq!(
+            print(&mut calculator, ":mode"),
+            "Mode: decimal (3 digits, rounding half-up)"
+        );
+    }
+
+    #[test]
+    fn test_factor() {
+        let mut calculator = Calculator::new();
+        assert_eq!(print(&mut calculator, ":factor 360"), "360 = 2^3 * 3^2 * 5");
+        assert_eq!(
+            print(&mut calculator, ":factor -2^61 + 1"),
+            "-2305843009213693951 = -1 * 2305843009213693951"
+        );
+        assert_eq!(
+            print(&mut calculator, ":factor 10!"),
+            "3628800 = 2^8 * 3^4 * 5^2 * 7"
+        );
+        assert_eq!(
+            print(&mut calculator, ":factor 1000000007 * 999999937"),
+            "999999943999999559 = 999999937 * 1000000007"
+        );
+        assert!(run(&mut calculator, ":factor 1").unwrap().is_err());
+        assert!(run(&mut calculator, ":factor 2.5").unwrap().is_err());
+        assert!(run(&mut calculator, ":factor").unwrap().is_err());
+        // Nothing is recorded
+        assert!(calculator.results().is_empty());
+    }
+
+    #[test]
+    fn test_fractions() {
+        let mut calculator = Calculator::new();
+        assert_eq!(
+            print(&mut calculator, ":mode rational"),
+            "Mode: rational (improper fractions)"
+        );
+        calculator.evaluate("x = -3/2").unwrap();
+        assert_eq!(print(&mut calculator, ":vars"), "  x = -3/2");
+        assert_eq!(
+            print(&mut calculator, ":fractions mixed"),
+            "Mode: rational (mixed fractions)"
+        );
+        assert_eq!(print(&mut calculator, ":vars"), "  x = -1 1/2");
+        print(&mut calculator, ":fractions decimal");
+        assert_eq!(
+            print(&mut calculator, ":history"),
+            "  $1   x = -3 / 2 = -1.5"
+        );
+        assert!(run(&mut calculator, ":fractions proper").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_complex() {
+        let mut calculator = Calculator::new();
+        assert!(calculator.evaluate("sqrt(-4)").is_err());
+        assert_eq!(
+            print(&mut calculator, ":mode complex"),
+            "Mode: complex (rectangular form)"
+        );
+        calculator.evaluate("z = sqrt(-4) + 1").unwrap();
+        assert_eq!(print(&mut calculator, ":vars"), "  z = 1+2i");
+        assert_eq!(
+            print(&mut calculator, ":complex polar"),
+            "Complex numbers are shown in polar form"
+        );
+        assert_eq!(
+            print(&mut calculator, ":history"),
+            "  $1   z = sqrt(-4) + 1 = 2.23606797749979 * e^(1.1071487177940904i)"
+        );
+        assert!(run(&mut calculator, ":complex cartesian").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_units() {
+        let mut calculator = Calculator::new();
+        let units = print(&mut calculator, ":units");
+        assert!(
+            units.starts_with("  m    = 1 m                            metre, takes prefixes\n")
+        );
+        assert!(units.contains("\n  mph  = 0.44704 m/s                    mile per hour\n"));
+        assert!(units.ends_with("Prefixes: Y Z E P T G M k h da d c m u µ n p f a z y"));
+
+        calculator.evaluate("speed = 60 mph to m/s").unwrap();
+        assert_eq!(print(&mut calculator, ":vars"), "  speed = 26.8224 m/s");
+        let script = session_script(&calculator);
+        let mut restored = Calculator::new();
+        restored.evaluate(script.lines().nth(2).unwrap()).unwrap();
+        assert_eq!(
+            restored.environment().variable("speed"),
+            calculator.environment().variable("speed")
+        );
+    }
+
+    /// Saves `calculator` and loads the file into a new session.
+    fn save_and_reload(calculator: &mut Calculator, name: &str) -> Calculator {
+        let path = std::env::temp_dir().join(format!(
+            "rust_calculator_{}_{}.calc",
+            std::process::id(),
+            name
+        ));
+        let path = path.to_str().unwrap();
+        print(calculator, &format!(":save {}", path));
+        let mut restored = Calculator::new();
+        print(&mut restored, &format!(":load {}", path));
+        fs::remove_file(path).unwrap();
+        restored
+    }
+
+    fn assert_round_trip(lines: &[&str], name: &str) {
+        let mut calculator = Calculator::new();
+        for line in lines {
+            if run(&mut calculator, line).is_none() {
+                calculator.evaluate(line).unwrap();
+            }
+        }
+        let restored = save_and_reload(&mut calculator, name);
+        for (name, value) in calculator.environment().variables() {
+            assert_eq!(
+                restored.environment().variable(name),
+                Some(value),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(
+            restored.environment().mode(),
+            calculator.environment().mode()
+        );
+        assert_eq!(
+            restored.environment().decimal(),
+            calculator.environment().decimal()
+        );
+    }
+
+    #[test]
+    fn test_save_float_mode() {
+        assert_round_trip(
+            &[
+                "whole = 3.0",
+                "sum = 0.1 + 0.2",
+                "huge = 1e300",
+                "tiny = -5e-324",
+                "exact = 2^70",
+                "speed = 60 mi/h to m/s",
+                "z = 0.1 + 2i",
+                "w = -2.5i",
+            ],
+            "float",
+        );
+    }
+
+    #[test]
+    fn test_save_decimal_mode() {
+        assert_round_trip(
+            &[
+                ":mode decimal",
+                ":precision 50",
+                "third = 1 / 3",
+                ":precision 10",
+                ":rounding up",
+                "money = 0.1 + 0.2",
+                "rate = 1.25e-8",
+            ],
+            "decimal",
+        );
+    }
+
+    #[test]
+    fn test_save_rational_mode() {
+        assert_round_trip(
+            &[
+                ":mode rational",
+                ":fractions mixed",
+                "x = 7 / 3",
+                "y = -1 / 6",
+                "n = 4",
+            ],
+            "rational",
+        );
+    }
+
+    #[test]
+    fn test_save_integer_mode() {
+        assert_round_trip(&[":mode int", "n = 30!", "root = sqrt(2)"], "integer");
+    }
+
+    #[test]
+    fn test_save_complex_mode() {
+        assert_round_trip(
+            &[
+                ":mode complex",
+                ":complex polar",
+                "z = sqrt(-2) + 1 / 3",
+                "w = 3 + 4i",
+                "r = 2.5",
+            ],
+            "complex",
+        );
+    }
+
+    #[test]
+    fn test_save_with_imaginary_unit_shadowed() {
+        assert_round_trip(
+            &[":mode complex", "z = 2 + 3 * sqrt(-1)", "i = 5"],
+            "shadowed",
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path =
+            std::env::temp_dir().join(format!("rust_calculator_{}.calc", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut calculator = Calculator::new();
+        calculator.evaluate("third = 1 / 3").unwrap();
+        calculator.evaluate("big = -inf").unwrap();
+        calculator.evaluate("area(r) = pi * r^2").unwrap();
+        print(&mut calculator, &format!(":save {}", path));
+
+        let mut restored = Calculator::new();
+        assert_eq!(
+            print(&mut restored, &format!(":load {}", path)),
+            format!("Loaded 7 statements from {}", path)
+        );
+        assert_eq!(
+            restored.environment().variable("third"),
+            Some(&(1.0 / 3.0).into())
+        );
+        assert_eq!(
+            restored.environment().variable("big"),
+            Some(&f64::NEG_INFINITY.into())
+        );
+        assert_eq!(
+            restored.evaluate("area(1)").unwrap(),
+            Some(std::f64::consts::PI.into())
+        );
+
+        fs::write(path, "x = 1\ny = 1 / 0\n").unwrap();
+        let Some(Err(message)) = run(&mut restored, &format!(":load {}", path)) else {
+            panic!("expected an error");
+        };
+        assert!(message.contains(&format!("{}:2:9", path)));
+        fs::remove_file(path).unwrap();
+
+        assert!(run(&mut restored, ":load").unwrap().is_err());
+        assert!(run(&mut restored, ":load /no/such/file").unwrap().is_err());
+    }
+}
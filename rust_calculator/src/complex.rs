@@ -0,0 +1,225 @@
+//! Complex numbers, written like `3+4i`.
+//!
+//! Any calculation with a complex operand is complex, in every mode, and
+//! results whose imaginary part is zero become real again. Complex mode
+//! also gives real arguments outside a real function's domain their
+//! complex result, so `sqrt(-1)` is `i` and `(-8)^(1/3)` is about
+//! `1+1.732i` instead of an error. Both parts are `f64`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use num_complex::Complex64;
+
+/// The name of the imaginary unit, unless a variable has taken it.
+pub const UNIT: &str = "i";
+
+/// Functions that refuse some real arguments whose result is complex, like
+/// `sqrt(-1)`. Complex mode calls their complex version for those.
+pub const BEYOND_REALS: &[&str] = &[
+    "sqrt", "ln", "log10", "log2", "log", "asin", "acos", "acosh", "atanh",
+];
+
+/// How complex numbers are printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ComplexStyle {
+    /// `3+4i`.
+    #[default]
+    Rectangular,
+    /// `5 * e^(0.9272952180016122i)`, the length and angle in radians.
+    Polar,
+}
+
+impl ComplexStyle {
+    /// Every style's name, as `--complex` and `:complex` accept them.
+    pub const NAMES: &'static [&'static str] = &["rectangular", "polar"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ComplexStyle::Rectangular => "rectangular",
+            ComplexStyle::Polar => "polar",
+        }
+    }
+}
+
+impl fmt::Display for ComplexStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ComplexStyle {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<ComplexStyle, String> {
+        match name.to_lowercase().as_str() {
+            "rectangular" | "rect" => Ok(ComplexStyle::Rectangular),
+            "polar" => Ok(ComplexStyle::Polar),
+            _ => Err(format!(
+                "unknown complex style '{}', expected one of {}",
+                name,
+                ComplexStyle::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Prints `value` in `style`. Both forms read back as the same value.
+pub fn format(value: &Complex64, style: ComplexStyle) -> String {
+    match style {
+        ComplexStyle::Rectangular => {
+            let imaginary = match value.im {
+                1.0 => "i".to_string(),
+                -1.0 => "-i".to_string(),
+                im => format!("{}i", im),
+            };
+            if value.re == 0.0 {
+                imaginary
+            } else if imaginary.starts_with('-') {
+                format!("{}{}", value.re, imaginary)
+            } else {
+                format!("{}+{}", value.re, imaginary)
+            }
+        }
+        ComplexStyle::Polar => {
+            let (length, angle) = value.to_polar();
+            format!("{} * e^({}i)", length, angle)
+        }
+    }
+}
+
+/// `base ^ exponent`. Whole exponents multiply, so `(1+2i)^2` is exactly
+/// `-3+4i`. A zero base must have an exponent with a positive real part.
+pub fn pow(base: Complex64, exponent: Complex64) -> Complex64 {
+    if base == Complex64::ZERO {
+        return Complex64::ZERO;
+    }
+    let whole = exponent.im == 0.0 && exponent.re.fract() == 0.0;
+    if whole && exponent.re.abs() <= f64::from(i32::MAX) {
+        base.powi(exponent.re as i32)
+    } else {
+        base.powc(exponent)
+    }
+}
+
+/// The complex version of the built-in function `name`, or `None` if it
+/// has none, like `floor` or `max`. The argument count has already been
+/// checked.
+pub fn call(name: &str, args: &[Complex64]) -> Option<Result<Complex64, String>> {
+    let z = args[0];
+    let result = match name {
+        "sqrt" => Ok(z.sqrt()),
+        "cbrt" => Ok(z.cbrt()),
+        "abs" => Ok(z.norm().into()),
+        "re" => Ok(z.re.into()),
+        "im" => Ok(z.im.into()),
+        "arg" => Ok(z.arg().into()),
+        "conj" => Ok(z.conj()),
+        "exp" => Ok(z.exp()),
+        "ln" => logarithm(name, z),
+        "log10" => logarithm(name, z).map(|ln| ln / std::f64::consts::LN_10),
+        "log2" => logarithm(name, z).map(|ln| ln / std::f64::consts::LN_2),
+        "log" => {
+            let base = args[0];
+            if base == Complex64::ZERO || base == Complex64::ONE {
+                Err("log needs a base other than 0 and 1".to_string())
+            } else {
+                logarithm(name, args[1]).map(|ln| ln / base.ln())
+            }
+        }
+        "sin" => Ok(z.sin()),
+        "cos" => Ok(z.cos()),
+        "tan" => Ok(z.tan()),
+        "asin" => Ok(z.asin()),
+        "acos" => Ok(z.acos()),
+        "atan" if z == Complex64::I || z == -Complex64::I => {
+            Err("atan is undefined at i and -i".to_string())
+        }
+        "atan" => Ok(z.atan()),
+        "sinh" => Ok(z.sinh()),
+        "cosh" => Ok(z.cosh()),
+        "tanh" => Ok(z.tanh()),
+        "asinh" => Ok(z.asinh()),
+        "acosh" => Ok(z.acosh()),
+        "atanh" if z == Complex64::ONE || z == -Complex64::ONE => {
+            Err("atanh is undefined at 1 and -1".to_string())
+        }
+        "atanh" => Ok(z.atanh()),
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// The natural logarithm of `z` for the function `name`, which has none
+/// at zero.
+fn logarithm(name: &str, z: Complex64) -> Result<Complex64, String> {
+    if z == Complex64::ZERO {
+        Err(format!("{} is undefined at 0", name))
+    } else {
+        Ok(z.ln())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(re: f64, im: f64) -> Complex64 {
+        Complex64::new(re, im)
+    }
+
+    fn close(a: Complex64, b: Complex64) -> bool {
+        (a - b).norm() < 1e-12
+    }
+
+    #[test]
+    fn test_format() {
+        let rectangular = |value| format(&value, ComplexStyle::Rectangular);
+        assert_eq!(rectangular(c(3.0, 4.0)), "3+4i");
+        assert_eq!(rectangular(c(0.5, -2.0)), "0.5-2i");
+        assert_eq!(rectangular(c(0.0, 1.0)), "i");
+        assert_eq!(rectangular(c(-1.0, -1.0)), "-1-i");
+        assert_eq!(
+            format(&c(0.0, 2.0), ComplexStyle::Polar),
+            "2 * e^(1.5707963267948966i)"
+        );
+        for name in ComplexStyle::NAMES {
+            assert_eq!(name.parse::<ComplexStyle>().unwrap().name(), *name);
+        }
+        assert!("cartesian".parse::<ComplexStyle>().is_err());
+    }
+
+    #[test]
+    fn test_powers() {
+        assert_eq!(pow(c(1.0, 2.0), c(2.0, 0.0)), c(-3.0, 4.0));
+        assert_eq!(pow(c(0.0, 1.0), c(-1.0, 0.0)), c(0.0, -1.0));
+        assert_eq!(pow(c(0.0, 0.0), c(0.5, 0.0)), c(0.0, 0.0));
+        let root = pow(c(-8.0, 0.0), c(1.0 / 3.0, 0.0));
+        assert!(close(root, c(1.0, 3f64.sqrt())));
+        // e^(i pi) = -1
+        let euler = pow(c(std::f64::consts::E, 0.0), c(0.0, std::f64::consts::PI));
+        assert!(close(euler, c(-1.0, 0.0)));
+    }
+
+    #[test]
+    fn test_functions() {
+        let call = |name, args: &[Complex64]| call(name, args).unwrap().unwrap();
+        assert_eq!(call("sqrt", &[c(-4.0, 0.0)]), c(0.0, 2.0));
+        assert_eq!(call("abs", &[c(3.0, -4.0)]), c(5.0, 0.0));
+        assert_eq!(call("conj", &[c(3.0, -4.0)]), c(3.0, 4.0));
+        assert_eq!(call("im", &[c(3.0, -4.0)]), c(-4.0, 0.0));
+        assert!(close(
+            call("ln", &[c(-1.0, 0.0)]),
+            c(0.0, std::f64::consts::PI)
+        ));
+        let log = call("log", &[c(2.0, 0.0), c(-8.0, 0.0)]);
+        assert!(close(
+            log,
+            c(3.0, std::f64::consts::PI / std::f64::consts::LN_2)
+        ));
+        assert!(close(call("asin", &[c(2.0, 0.0)]).sin(), c(2.0, 0.0)));
+        assert!(super::call("ln", &[c(0.0, 0.0)]).unwrap().is_err());
+        assert!(super::call("atanh", &[c(1.0, 0.0)]).unwrap().is_err());
+        assert!(super::call("floor", &[c(1.0, 1.0)]).is_none());
+    }
+}
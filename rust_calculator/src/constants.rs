@@ -0,0 +1,89 @@
+/// A named constant such as `pi`, resolved when a bare name is evaluated.
+#[derive(Debug)]
+pub struct Constant {
+    pub name: &'static str,
+    pub value: f64,
+    /// SI unit of the value; empty for plain numbers.
+    pub unit: &'static str,
+    pub description: &'static str,
+    /// Physical constants have short names like `c` and `h` that are easy
+    /// to hit by accident, so they are only available once enabled.
+    pub physics: bool,
+}
+
+/// Finds the constant called `name`, whether or not it is enabled.
+pub fn lookup(name: &str) -> Option<&'static Constant> {
+    CONSTANTS.iter().find(|constant| constant.name == name)
+}
+
+const fn math(name: &'static str, value: f64, description: &'static str) -> Constant {
+    Constant {
+        name,
+        value,
+        unit: "",
+        description,
+        physics: false,
+    }
+}
+
+const fn physics(
+    name: &'static str,
+    value: f64,
+    unit: &'static str,
+    description: &'static str,
+) -> Constant {
+    Constant {
+        name,
+        value,
+        unit,
+        description,
+        physics: true,
+    }
+}
+
+/// Every constant, in the order they are listed.
+pub const CONSTANTS: &[Constant] = &[
+    math(
+        "pi",
+        std::f64::consts::PI,
+        "ratio of a circle's circumference to its diameter",
+    ),
+    math("e", std::f64::consts::E, "base of the natural logarithm"),
+    math("tau", std::f64::consts::TAU, "full turn in radians, 2 * pi"),
+    math(
+        "phi",
+        1.618_033_988_749_895,
+        "golden ratio, (1 + sqrt(5)) / 2",
+    ),
+    math("inf", f64::INFINITY, "positive infinity"),
+    math("nan", f64::NAN, "not a number"),
+    physics("c", 299_792_458.0, "m/s", "speed of light in vacuum"),
+    physics(
+        "G",
+        6.674_30e-11,
+        "m^3/(kg s^2)",
+        "Newtonian constant of gravitation",
+    ),
+    physics("h", 6.626_070_15e-34, "J s", "Planck constant"),
+    physics("k_B", 1.380_649e-23, "J/K", "Boltzmann constant"),
+    physics("N_A", 6.022_140_76e23, "1/mol", "Avogadro constant"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup("pi").unwrap().value, std::f64::consts::PI);
+        assert!(lookup("k_B").unwrap().physics);
+        assert!(!lookup("tau").unwrap().physics);
+        assert!(lookup("PI").is_none());
+    }
+
+    #[test]
+    fn test_golden_ratio() {
+        let phi = lookup("phi").unwrap().value;
+        assert_eq!(phi, (1.0 + 5f64.sqrt()) / 2.0);
+    }
+}
@@ -0,0 +1,431 @@
+//! Base-10 arithmetic for decimal mode.
+//!
+//! Every result is rounded to the session's precision, counted in
+//! significant digits, using its rounding mode. Parsing, `+ - * / // % mod`,
+//! integer powers and the functions in [`call`] are exact up to that
+//! rounding. Constants and the remaining functions, such as `ln` and `sin`,
+//! are computed as `f64` and converted, so they are only good to about 16
+//! digits.
+
+use std::fmt;
+use std::num::NonZeroU64;
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, Context, RoundingMode};
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+/// Significant digits when none are chosen, as in Python's `decimal`.
+pub const DEFAULT_PRECISION: u64 = 28;
+
+/// The most significant digits a session can ask for.
+pub const MAX_PRECISION: u64 = 1000;
+
+/// Integer powers beyond this go through `f64`, which reports them as an
+/// overflow instead of building a number with billions of digits.
+const MAX_EXACT_EXPONENT: i64 = 1_000_000_000;
+
+/// Decimals are kept between about `1e-100000` and `1e100000`. Adding or
+/// dividing lines both numbers up digit by digit, so `1e999999999 + 1`
+/// would build a billion-digit integer; literals further out are read as
+/// `f64`, and results further out are an overflow.
+pub const MAX_SCALE: i64 = 100_000;
+
+/// How results are rounded to the precision.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Halves go to the even neighbour, so `2.5` becomes `2` and `3.5`
+    /// becomes `4`. Also known as banker's rounding.
+    #[default]
+    HalfEven,
+    /// Halves go away from zero, as taught in school.
+    HalfUp,
+    /// Halves go towards zero.
+    HalfDown,
+    /// Away from zero.
+    Up,
+    /// Towards zero, i.e. truncation.
+    Down,
+    /// Towards positive infinity.
+    Ceiling,
+    /// Towards negative infinity.
+    Floor,
+}
+
+impl Rounding {
+    /// Every rounding mode's name, as `--rounding` and `:rounding` accept them.
+    pub const NAMES: &'static [&'static str] = &[
+        "half-even",
+        "half-up",
+        "half-down",
+        "up",
+        "down",
+        "ceiling",
+        "floor",
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rounding::HalfEven => "half-even",
+            Rounding::HalfUp => "half-up",
+            Rounding::HalfDown => "half-down",
+            Rounding::Up => "up",
+            Rounding::Down => "down",
+            Rounding::Ceiling => "ceiling",
+            Rounding::Floor => "floor",
+        }
+    }
+
+    fn mode(self) -> RoundingMode {
+        match self {
+            Rounding::HalfEven => RoundingMode::HalfEven,
+            Rounding::HalfUp => RoundingMode::HalfUp,
+            Rounding::HalfDown => RoundingMode::HalfDown,
+            Rounding::Up => RoundingMode::Up,
+            Rounding::Down => RoundingMode::Down,
+            Rounding::Ceiling => RoundingMode::Ceiling,
+            Rounding::Floor => RoundingMode::Floor,
+        }
+    }
+}
+
+impl fmt::Display for Rounding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Rounding, String> {
+        let name = name.to_lowercase().replace('_', "-");
+        let rounding = match name.as_str() {
+            "half-even" => Rounding::HalfEven,
+            "half-up" => Rounding::HalfUp,
+            "half-down" => Rounding::HalfDown,
+            "up" => Rounding::Up,
+            "down" => Rounding::Down,
+            "ceiling" => Rounding::Ceiling,
+            "floor" => Rounding::Floor,
+            _ => {
+                return Err(format!(
+                    "unknown rounding '{}', expected one of {}",
+                    name,
+                    Rounding::NAMES.join(", ")
+                ))
+            }
+        };
+        Ok(rounding)
+    }
+}
+
+/// The precision and rounding of decimal mode. They are kept while the
+/// session is in another mode, so switching back restores them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    precision: u64,
+    rounding: Rounding,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            precision: DEFAULT_PRECISION,
+            rounding: Rounding::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Significant digits results are rounded to.
+    pub fn precision(&self) -> u64 {
+        self.precision
+    }
+
+    pub fn rounding(&self) -> Rounding {
+        self.rounding
+    }
+
+    /// Fails unless `precision` is between 1 and [`MAX_PRECISION`].
+    pub fn with_precision(self, precision: u64) -> Result<Settings, String> {
+        if !(1..=MAX_PRECISION).contains(&precision) {
+            return Err(format!(
+                "precision must be between 1 and {} digits",
+                MAX_PRECISION
+            ));
+        }
+        Ok(Settings { precision, ..self })
+    }
+
+    pub fn with_rounding(self, rounding: Rounding) -> Settings {
+        Settings { rounding, ..self }
+    }
+
+    fn context(&self) -> Context {
+        let precision = NonZeroU64::new(self.precision).unwrap_or(NonZeroU64::MIN);
+        Context::new(precision, self.rounding.mode())
+    }
+
+    /// Rounds `value` to this precision.
+    pub fn round(&self, value: &BigDecimal) -> BigDecimal {
+        self.context().round_decimal_ref(value)
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} digits, rounding {}", self.precision, self.rounding)
+    }
+}
+
+/// Reads a number literal such as `1.5e-3` exactly, unless its exponent is
+/// beyond [`MAX_SCALE`].
+pub fn parse(text: &str) -> Option<BigDecimal> {
+    text.parse().ok().filter(in_range)
+}
+
+/// Whether `value` is within [`MAX_SCALE`] powers of ten, so calculating
+/// with it takes a sensible time.
+pub fn in_range(value: &BigDecimal) -> bool {
+    value.fractional_digit_count().abs() <= MAX_SCALE
+}
+
+/// The decimal that prints like `value` does, so `0.1` becomes exactly
+/// `0.1` rather than the binary fraction closest to it. Infinity and NaN
+/// have no decimal form.
+pub fn from_f64(value: f64) -> Option<BigDecimal> {
+    if value.is_finite() {
+        parse(&format!("{:e}", value))
+    } else {
+        None
+    }
+}
+
+/// The nearest `f64` to `value`.
+pub fn to_f64(value: &BigDecimal) -> f64 {
+    value.to_f64().unwrap_or(f64::NAN)
+}
+
+/// Prints `value` without trailing zeros, in scientific notation when it
+/// is very large or very small.
+pub fn to_plain_string(value: &BigDecimal) -> String {
+    let value = value.normalized();
+    if value.is_zero() || (-6..21).contains(&value.order_of_magnitude()) {
+        value.to_plain_string()
+    } else {
+        value.to_scientific_notation()
+    }
+}
+
+/// Both numbers as integer multiples of the same power of ten, and that
+/// power's scale, or `None` if either is out of range.
+fn align(a: &BigDecimal, b: &BigDecimal) -> Option<(BigInt, BigInt, i64)> {
+    if !in_range(a) || !in_range(b) {
+        return None;
+    }
+    let scale = a.fractional_digit_count().max(b.fractional_digit_count());
+    let (a, _) = a.with_scale(scale).into_bigint_and_scale();
+    let (b, _) = b.with_scale(scale).into_bigint_and_scale();
+    Some((a, b, scale))
+}
+
+/// `a / b`. The quotient is computed to two more digits than the precision,
+/// plus a final digit that records whether anything was left over, so it is
+/// rounded once and correctly. `b` must not be zero.
+pub fn divide(a: &BigDecimal, b: &BigDecimal, settings: &Settings) -> BigDecimal {
+    let (a, a_scale) = a.as_bigint_and_scale();
+    let (b, b_scale) = b.as_bigint_and_scale();
+    let a_digits = a.magnitude().to_string().len() as i64;
+    let b_digits = b.magnitude().to_string().len() as i64;
+    let shift = (settings.precision as i64 + 2 + b_digits - a_digits).max(0);
+    let numerator = &*a * BigInt::from(10).pow(shift as u32);
+    let (quotient, remainder) = numerator.div_rem(&b);
+    let sticky = if remainder.is_zero() {
+        BigInt::zero()
+    } else {
+        // Away from zero, in the direction of the quotient
+        numerator.signum() * b.signum()
+    };
+    let quotient = BigDecimal::new(quotient * 10 + sticky, a_scale - b_scale + shift + 1);
+    settings.round(&quotient)
+}
+
+/// `a // b`, rounded towards negative infinity, or `None` if either number
+/// is out of range. `b` must not be zero.
+pub fn floor_divide(a: &BigDecimal, b: &BigDecimal, settings: &Settings) -> Option<BigDecimal> {
+    let (a, b, _) = align(a, b)?;
+    Some(settings.round(&BigDecimal::from(a.div_floor(&b))))
+}
+
+/// `a % b`, which takes the sign of `a`, or `None` if either number is out
+/// of range. `b` must not be zero.
+pub fn remainder(a: &BigDecimal, b: &BigDecimal, settings: &Settings) -> Option<BigDecimal> {
+    let (a, b, scale) = align(a, b)?;
+    Some(settings.round(&BigDecimal::new(a % b, scale)))
+}
+
+/// `a mod b`, always in `0..|b|`, or `None` if either number is out of
+/// range. `b` must not be zero.
+pub fn modulo(a: &BigDecimal, b: &BigDecimal, settings: &Settings) -> Option<BigDecimal> {
+    let (a, b, scale) = align(a, b)?;
+    let mut result = a % &b;
+    if result.is_negative() {
+        result += b.abs();
+    }
+    Some(settings.round(&BigDecimal::new(result, scale)))
+}
+
+/// `base ^ exponent` for integer exponents of a sensible size, or `None`
+/// when the power has to be computed as `f64`. A zero base with a negative
+/// exponent must already have been ruled out.
+pub fn pow(base: &BigDecimal, exponent: &BigDecimal, settings: &Settings) -> Option<BigDecimal> {
+    if !exponent.is_integer() {
+        return None;
+    }
+    let exponent = exponent.to_i64()?;
+    if exponent.abs() > MAX_EXACT_EXPONENT {
+        return None;
+    }
+    Some(base.powi_with_context(exponent, &settings.context()))
+}
+
+/// The decimal version of the built-in function `name`, or `None` if it
+/// has none or not for these arguments, in which case the `f64` version is
+/// used. Arguments outside a function's domain always return `None`, so the
+/// error comes from the `f64` version too.
+pub fn call(name: &str, args: &[BigDecimal], settings: &Settings) -> Option<BigDecimal> {
+    let round_to_integer = |mode: RoundingMode| settings.round(&args[0].with_scale_round(0, mode));
+    let result = match name {
+        "sqrt" => args[0].sqrt_with_context(&settings.context())?,
+        "cbrt" => args[0].cbrt_with_context(&settings.context()),
+        "abs" => settings.round(&args[0].abs()),
+        "floor" => round_to_integer(RoundingMode::Floor),
+        "ceil" => round_to_integer(RoundingMode::Ceiling),
+        "round" => round_to_integer(RoundingMode::HalfUp),
+        "trunc" => round_to_integer(RoundingMode::Down),
+        "min" => settings.round(args.iter().min()?),
+        "max" => settings.round(args.iter().max()?),
+        "hypot" => {
+            let sum = args[0].square() + args[1].square();
+            sum.sqrt_with_context(&settings.context())?
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(text: &str) -> BigDecimal {
+        parse(text).unwrap()
+    }
+
+    fn settings(precision: u64, rounding: Rounding) -> Settings {
+        Settings::default()
+            .with_precision(precision)
+            .unwrap()
+            .with_rounding(rounding)
+    }
+
+    #[test]
+    fn test_division_rounds_once() {
+        let default = Settings::default();
+        assert_eq!(
+            divide(&d("1"), &d("3"), &default),
+            d("0.3333333333333333333333333333")
+        );
+        assert_eq!(
+            divide(&d("-2"), &d("3"), &default),
+            d("-0.6666666666666666666666666667")
+        );
+        assert_eq!(divide(&d("10"), &d("4"), &default), d("2.5"));
+        assert_eq!(divide(&d("1e30"), &d("0.001"), &default), d("1e33"));
+        // 0.125 is exactly halfway at two digits, 0.1251 is not
+        let two = settings(2, Rounding::HalfEven);
+        assert_eq!(divide(&d("1"), &d("8"), &two), d("0.12"));
+        assert_eq!(divide(&d("1.251"), &d("10"), &two), d("0.13"));
+        assert_eq!(
+            divide(&d("-1"), &d("8"), &settings(2, Rounding::Floor)),
+            d("-0.13")
+        );
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        let round = |rounding, text| settings(1, rounding).round(&d(text));
+        assert_eq!(round(Rounding::HalfEven, "2.5"), d("2"));
+        assert_eq!(round(Rounding::HalfEven, "3.5"), d("4"));
+        assert_eq!(round(Rounding::HalfUp, "2.5"), d("3"));
+        assert_eq!(round(Rounding::HalfDown, "2.5"), d("2"));
+        assert_eq!(round(Rounding::Up, "2.1"), d("3"));
+        assert_eq!(round(Rounding::Down, "-2.9"), d("-2"));
+        assert_eq!(round(Rounding::Ceiling, "-2.9"), d("-2"));
+        assert_eq!(round(Rounding::Floor, "-2.1"), d("-3"));
+        for name in Rounding::NAMES {
+            assert_eq!(name.parse::<Rounding>().unwrap().name(), *name);
+        }
+        assert_eq!("HALF_UP".parse(), Ok(Rounding::HalfUp));
+        assert!("nearest".parse::<Rounding>().is_err());
+    }
+
+    #[test]
+    fn test_remainders() {
+        let default = Settings::default();
+        assert_eq!(floor_divide(&d("-7"), &d("2"), &default), Some(d("-4")));
+        assert_eq!(floor_divide(&d("7.5"), &d("0.5"), &default), Some(d("15")));
+        assert_eq!(remainder(&d("-7"), &d("3"), &default), Some(d("-1")));
+        assert_eq!(remainder(&d("7.5"), &d("2"), &default), Some(d("1.5")));
+        assert_eq!(modulo(&d("-7"), &d("3"), &default), Some(d("2")));
+        assert_eq!(modulo(&d("-7"), &d("-3"), &default), Some(d("2")));
+        assert_eq!(modulo(&d("-0.5"), &d("0.2"), &default), Some(d("0.1")));
+        let huge: BigDecimal = "1e99999999".parse().unwrap();
+        assert_eq!(floor_divide(&huge, &d("7"), &default), None);
+        assert_eq!(modulo(&d("1"), &huge, &default), None);
+    }
+
+    #[test]
+    fn test_powers_and_functions() {
+        let default = Settings::default();
+        assert_eq!(pow(&d("1.1"), &d("2"), &default), Some(d("1.21")));
+        assert_eq!(pow(&d("2"), &d("-2"), &default), Some(d("0.25")));
+        assert_eq!(pow(&d("2"), &d("0.5"), &default), None);
+        assert_eq!(pow(&d("2"), &d("1e20"), &default), None);
+        assert_eq!(
+            call("sqrt", &[d("2")], &default),
+            Some(d("1.414213562373095048801688724"))
+        );
+        assert_eq!(call("sqrt", &[d("-4")], &default), None);
+        assert_eq!(call("round", &[d("-2.5")], &default), Some(d("-3")));
+        assert_eq!(
+            call("max", &[d("0.1"), d("0.30"), d("0.2")], &default),
+            Some(d("0.3"))
+        );
+        assert_eq!(call("hypot", &[d("3"), d("4")], &default), Some(d("5")));
+        assert_eq!(call("sin", &[d("1")], &default), None);
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(from_f64(0.1), Some(d("0.1")));
+        assert_eq!(from_f64(6.626_070_15e-34), Some(d("6.62607015e-34")));
+        assert_eq!(from_f64(f64::NAN), None);
+        assert_eq!(parse("1e100000"), Some(BigDecimal::new(1.into(), -100_000)));
+        assert_eq!(parse("1e999999999"), None);
+        assert_eq!(parse("1e-5000000"), None);
+        assert_eq!(to_plain_string(&d("100.50")), "100.5");
+        assert_eq!(to_plain_string(&d("0.000001")), "0.000001");
+        assert_eq!(to_plain_string(&d("1.5e-7")), "1.5e-7");
+        assert_eq!(
+            to_plain_string(&d("1.6069380442589902755419620923e60")),
+            "1.6069380442589902755419620923e60"
+        );
+        assert!(Settings::default().with_precision(0).is_err());
+        assert!(Settings::default()
+            .with_precision(MAX_PRECISION + 1)
+            .is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+
+use crate::lexer::Span;
+
+/// An error tied to a location in the input, rendered rustc-style:
+///
+/// ```text
+/// error[E0002]: Unsupported operator: x
+///   |
+///   | 5 x 3
+///   |   ^
+///   = help: did you mean `*`?
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(code: &'static str, message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic {
+            code,
+            message: message.into(),
+            span,
+            help: None,
+        }
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Diagnostic {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Echoes `input` and underlines the offending span with `^~~~`.
+    pub fn render(&self, input: &str) -> String {
+        self.render_snippet(None, "", input, self.span.start, self.span.end)
+    }
+
+    /// Like `render`, but for a span into a whole file: the header points at
+    /// `path:line:column` and only the line the span starts on is echoed.
+    ///
+    /// ```text
+    /// error[E0003]: Cannot divide by zero!
+    ///  --> budget.calc:3:13
+    ///   |
+    /// 3 | monthly = 0 / 0
+    ///   |             ^
+    /// ```
+    pub fn render_in_file(&self, path: &str, source: &str) -> String {
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line = source[line_start..line_end].trim_end_matches('\r');
+        let number = source[..line_start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count() + 1;
+        let location = format!("{}:{}:{}", path, number, column);
+        self.render_snippet(
+            Some(&location),
+            &number.to_string(),
+            line,
+            start - line_start,
+            self.span.end.min(line_end).saturating_sub(line_start),
+        )
+    }
+
+    /// Renders the message, an optional location and one line of input with
+    /// the byte range `start..end` of that line underlined.
+    fn render_snippet(
+        &self,
+        location: Option<&str>,
+        label: &str,
+        line: &str,
+        start: usize,
+        end: usize,
+    ) -> String {
+        // Spans are byte offsets, but the underline has to line up with characters
+        let start = start.min(line.len());
+        let end = end.clamp(start, line.len());
+        let column = line[..start].chars().count();
+        let width = line[start..end].chars().count().max(1);
+        let gutter = " ".repeat(label.len().max(1));
+
+        let mut output = format!("error[{}]: {}\n", self.code, self.message);
+        if let Some(location) = location {
+            output.push_str(&format!("{}--> {}\n", gutter, location));
+        }
+        output.push_str(&format!("{} |\n", gutter));
+        output.push_str(&format!(
+            "{:>width$} | {}\n",
+            label,
+            line,
+            width = gutter.len()
+        ));
+        output.push_str(&format!(
+            "{} | {}^{}",
+            gutter,
+            " ".repeat(column),
+            "~".repeat(width - 1)
+        ));
+        if let Some(help) = &self.help {
+            output.push_str(&format!("\n{} = help: {}", gutter, help));
+        }
+        output
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_underlines_span() {
+        let diagnostic = Diagnostic::new("E0001", "'abc' is not a valid number", Span::new(0, 3));
+        assert_eq!(
+            diagnostic.render("abc + 3"),
+            "error[E0001]: 'abc' is not a valid number\n  |\n  | abc + 3\n  | ^~~"
+        );
+    }
+
+    #[test]
+    fn test_render_with_help() {
+        let diagnostic = Diagnostic::new("E0002", "Unsupported operator: x", Span::new(2, 3))
+            .with_help("did you mean `*`?");
+        assert_eq!(
+            diagnostic.render("5 x 3"),
+            "error[E0002]: Unsupported operator: x\n  |\n  | 5 x 3\n  |   ^\n  = help: did you mean `*`?"
+        );
+    }
+
+    #[test]
+    fn test_render_at_end_of_input() {
+        let diagnostic = Diagnostic::new("E0006", "Unexpected end of input", Span::new(3, 3));
+        assert!(diagnostic.render("5 +").ends_with("  | 5 +\n  |    ^"));
+    }
+
+    #[test]
+    fn test_render_in_file() {
+        let source = "# budget\nrent = 1200\nmonthly = rent / 0\n";
+        let start = source.find('/').unwrap();
+        let diagnostic = Diagnostic::new(
+            "E0003",
+            "Cannot divide by zero!",
+            Span::new(start, start + 1),
+        );
+        assert_eq!(
+            diagnostic.render_in_file("budget.calc", source),
+            "error[E0003]: Cannot divide by zero!\n --> budget.calc:3:16\n  |\n3 | monthly = rent / 0\n  |                ^"
+        );
+    }
+
+    #[test]
+    fn test_render_in_file_widens_the_gutter() {
+        let source = format!("{}1 +", "\n".repeat(11));
+        let diagnostic = Diagnostic::new(
+            "E0006",
+            "Unexpected end of input",
+            Span::new(source.len(), source.len()),
+        )
+        .with_help("add a number");
+        assert!(diagnostic
+            .render_in_file("long.calc", &source)
+            .ends_with("  --> long.calc:12:4\n   |\n12 | 1 +\n   |    ^\n   = help: add a number"));
+    }
+
+    #[test]
+    fn test_render_counts_characters_not_bytes() {
+        // "÷" is two bytes but one column wide
+        let diagnostic = Diagnostic::new("E0002", "Unsupported operator: ÷", Span::new(2, 4));
+        assert!(diagnostic.render("8 ÷ 2").ends_with("  |   ^"));
+    }
+}
@@ -0,0 +1,204 @@
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Config, Context, Editor, Helper};
+
+/// How many lines of history are kept between sessions.
+const HISTORY_SIZE: usize = 1000;
+
+/// Where the REPL reads its input from: a line editor with history and tab
+/// completion at a terminal, plain lines when input is piped in.
+pub enum Input {
+    Editor {
+        editor: Box<Editor<Completion, DefaultHistory>>,
+        history: Option<PathBuf>,
+    },
+    Plain,
+}
+
+impl Input {
+    /// Chooses the editor when stdin is a terminal. `commands` are completed
+    /// at the start of a line, alongside the names from the session.
+    pub fn new(commands: Vec<&'static str>) -> Input {
+        if !io::stdin().is_terminal() {
+            return Input::Plain;
+        }
+        let config = Config::builder()
+            .max_history_size(HISTORY_SIZE)
+            .map(|builder| builder.build())
+            .unwrap_or_default();
+        let Ok(mut editor) = Editor::with_config(config) else {
+            return Input::Plain;
+        };
+        editor.set_helper(Some(Completion {
+            names: Vec::new(),
+            commands,
+        }));
+        let history = history_path();
+        if let Some(path) = &history {
+            // A missing file only means this is the first session
+            let _ = editor.load_history(path);
+        }
+        Input::Editor {
+            editor: Box::new(editor),
+            history,
+        }
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        matches!(self, Input::Editor { .. })
+    }
+
+    /// Reads one line, or `None` at the end of input. `prompt` is only shown
+    /// by the editor, and `names` are what tab completes to, so they should
+    /// come from the current session. Ctrl-C is reported as an
+    /// [`io::ErrorKind::Interrupted`] error.
+    pub fn read_line(&mut self, prompt: &str, names: Vec<String>) -> io::Result<Option<String>> {
+        let editor = match self {
+            Input::Editor { editor, .. } => editor,
+            Input::Plain => {
+                let mut line = String::new();
+                return match io::stdin().read_line(&mut line)? {
+                    0 => Ok(None),
+                    _ => Ok(Some(line)),
+                };
+            }
+        };
+        if let Some(helper) = editor.helper_mut() {
+            helper.names = names;
+        }
+        match editor.readline(prompt) {
+            Ok(line) => {
+                let _ = editor.add_history_entry(line.as_str());
+                Ok(Some(line))
+            }
+            Err(ReadlineError::Interrupted) => Err(io::ErrorKind::Interrupted.into()),
+            Err(ReadlineError::Eof) => Ok(None),
+            Err(ReadlineError::Io(error)) => Err(error),
+            Err(error) => Err(io::Error::other(error)),
+        }
+    }
+
+    /// Adds this session's lines to the history file, creating its
+    /// directory if needed.
+    pub fn save_history(&mut self) -> io::Result<()> {
+        let Input::Editor {
+            editor,
+            history: Some(path),
+        } = self
+        else {
+            return Ok(());
+        };
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        editor.append_history(path).map_err(|error| match error {
+            ReadlineError::Io(error) => error,
+            error => io::Error::other(error),
+        })
+    }
+}
+
+/// `$XDG_DATA_HOME/rust_calculator/history`, which is usually
+/// `~/.local/share/rust_calculator/history`.
+fn history_path() -> Option<PathBuf> {
+    let data = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))?;
+    Some(data.join("rust_calculator").join("history"))
+}
+
+/// Tab completion of names and REPL commands.
+pub struct Completion {
+    names: Vec<String>,
+    commands: Vec<&'static str>,
+}
+
+impl Completion {
+    /// Where the word before the cursor starts, and the names it could be
+    /// completed to. A leading `:` is part of the word, so `:va` completes
+    /// to `:vars`.
+    fn candidates(&self, before: &str) -> (usize, Vec<String>) {
+        let mut start = before
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+            .last()
+            .map_or(before.len(), |(i, _)| i);
+        if before[..start].trim_start() == ":" {
+            start -= 1;
+        }
+        let word = &before[start..];
+        let command_position = before[..start].trim().is_empty();
+        let commands = self.commands.iter().filter(|_| command_position);
+        let mut candidates: Vec<String> = commands
+            .map(|command| command.to_string())
+            .chain(self.names.iter().cloned())
+            .filter(|name| name.starts_with(word))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        (start, candidates)
+    }
+}
+
+impl Completer for Completion {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.candidates(&line[..pos]))
+    }
+}
+
+impl Hinter for Completion {
+    type Hint = String;
+}
+
+impl Highlighter for Completion {}
+
+impl Validator for Completion {}
+
+impl Helper for Completion {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete(before: &str) -> (usize, Vec<String>) {
+        let completion = Completion {
+            names: vec!["sqrt".to_string(), "sin".to_string(), "salary".to_string()],
+            commands: vec![":vars", ":funcs", "quit"],
+        };
+        completion.candidates(before)
+    }
+
+    #[test]
+    fn test_completes_names_before_the_cursor() {
+        assert_eq!(complete("s"), (0, vec!["salary".into(), "sin".into(), "sqrt".into()]));
+        assert_eq!(complete("2 * sq"), (4, vec!["sqrt".into()]));
+        assert_eq!(complete("max(sal"), (4, vec!["salary".into()]));
+        assert_eq!(complete("2 + x"), (4, vec![]));
+    }
+
+    #[test]
+    fn test_completes_commands_at_the_start_only() {
+        assert_eq!(complete(":v"), (0, vec![":vars".into()]));
+        assert_eq!(complete("  :f"), (2, vec![":funcs".into()]));
+        assert_eq!(complete("q"), (0, vec!["quit".into()]));
+        assert_eq!(complete("1 + q"), (4, vec![]));
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::ast::Expr;
+use crate::complex::{self, ComplexStyle};
+use crate::constants::{self, Constant};
+use crate::decimal;
+use crate::eval::IF;
+use crate::functions::FUNCTIONS;
+use crate::number::{Mode, Number};
+use crate::rational::{self, FractionStyle};
+use crate::units::UNITS;
+
+/// Names that always refer to the most recent result.
+pub const LAST_RESULT_NAMES: &[&str] = &["ans", "_"];
+
+/// Settings and names that expressions are evaluated against.
+///
+/// `ans` and `_` refer to the last result and `$1`, `$2`, ... to every result
+/// so far. Other variables are looked up before constants. Enabled constants cannot be
+/// assigned to, but a variable defined while the physical constants were
+/// off keeps shadowing its constant after they are turned on. `i` is the
+/// imaginary unit unless a variable is called `i`. The mode decides which
+/// kind of [`Number`] calculations produce.
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    physics: bool,
+    mode: Mode,
+    decimal: decimal::Settings,
+    fractions: FractionStyle,
+    complex: ComplexStyle,
+    variables: BTreeMap<String, Number>,
+    functions: BTreeMap<String, UserFunction>,
+    results: Vec<Number>,
+}
+
+/// A function defined in the session, e.g. `f(x, y) = x^2 + y`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserFunction {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Expr,
+}
+
+impl fmt::Display for UserFunction {
+    /// Prints the definition back in normalized form.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}({}) = {}",
+            self.name,
+            self.params.join(", "),
+            self.body
+        )
+    }
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment::default()
+    }
+
+    /// Whether physical constants such as `c` and `h` can be used.
+    pub fn physics_enabled(&self) -> bool {
+        self.physics
+    }
+
+    pub fn set_physics(&mut self, enabled: bool) {
+        self.physics = enabled;
+    }
+
+    /// The kind of number calculations produce.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// The precision and rounding of decimal mode.
+    pub fn decimal(&self) -> decimal::Settings {
+        self.decimal
+    }
+
+    pub fn set_decimal(&mut self, settings: decimal::Settings) {
+        self.decimal = settings;
+    }
+
+    /// How results of rational mode are printed.
+    pub fn fractions(&self) -> FractionStyle {
+        self.fractions
+    }
+
+    pub fn set_fractions(&mut self, style: FractionStyle) {
+        self.fractions = style;
+    }
+
+    /// How complex numbers are printed.
+    pub fn complex(&self) -> ComplexStyle {
+        self.complex
+    }
+
+    pub fn set_complex(&mut self, style: ComplexStyle) {
+        self.complex = style;
+    }
+
+    /// Prints `value` for display, with fractions and complex numbers in
+    /// this environment's styles. [`Number`]'s own `Display` always prints
+    /// improper fractions and rectangular complex numbers.
+    pub fn format(&self, value: &Number) -> String {
+        match value {
+            Number::Rational(value) => rational::format(value, self.fractions, &self.decimal),
+            Number::Complex(value) => complex::format(value, self.complex),
+            value => value.to_string(),
+        }
+    }
+
+    /// Stores `value` under `name`, replacing any previous value.
+    pub fn set_variable(&mut self, name: &str, value: impl Into<Number>) {
+        self.variables.insert(name.to_string(), value.into());
+    }
+
+    pub fn variable(&self, name: &str) -> Option<&Number> {
+        self.variables.get(name)
+    }
+
+    /// All variables, sorted by name.
+    pub fn variables(&self) -> impl Iterator<Item = (&str, &Number)> + '_ {
+        self.variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    /// Stores a user-defined function, replacing any previous definition.
+    pub fn define_function(&mut self, function: UserFunction) {
+        self.functions.insert(function.name.clone(), function);
+    }
+
+    pub fn function(&self, name: &str) -> Option<&UserFunction> {
+        self.functions.get(name)
+    }
+
+    /// All user-defined functions, sorted by name.
+    pub fn functions(&self) -> impl Iterator<Item = &UserFunction> + '_ {
+        self.functions.values()
+    }
+
+    /// Records a result so later expressions can refer to it.
+    pub fn push_result(&mut self, value: impl Into<Number>) {
+        self.results.push(value.into());
+    }
+
+    /// Every result so far, oldest first; `$1` is `results()[0]`.
+    pub fn results(&self) -> &[Number] {
+        &self.results
+    }
+
+    /// The result `$index`, counting from 1.
+    pub fn result(&self, index: usize) -> Option<&Number> {
+        index.checked_sub(1).and_then(|i| self.results.get(i))
+    }
+
+    pub fn last_result(&self) -> Option<&Number> {
+        self.results.last()
+    }
+
+    /// The enabled constant called `name`, if there is one.
+    pub fn constant(&self, name: &str) -> Option<&'static Constant> {
+        constants::lookup(name).filter(|constant| !constant.physics || self.physics)
+    }
+
+    /// The constants that names currently resolve to.
+    pub fn constants(&self) -> impl Iterator<Item = &'static Constant> + '_ {
+        constants::CONSTANTS
+            .iter()
+            .filter(move |constant| !constant.physics || self.physics)
+    }
+
+    /// Every name an expression can currently refer to, sorted and without
+    /// duplicates: functions, enabled constants, variables, `ans`/`_`, the
+    /// imaginary unit `i` and the units without prefixes.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = FUNCTIONS
+            .iter()
+            .map(|function| function.name)
+            .chain([IF])
+            .chain(self.functions.keys().map(String::as_str))
+            .chain(self.constants().map(|constant| constant.name))
+            .chain(self.variables.keys().map(String::as_str))
+            .chain(LAST_RESULT_NAMES.iter().copied())
+            .chain([complex::UNIT])
+            .chain(UNITS.iter().map(|unit| unit.symbol))
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+}
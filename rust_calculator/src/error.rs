@@ -0,0 +1,470 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::diagnostic::Diagnostic;
+use crate::functions::Arity;
+use crate::lexer::Span;
+use crate::units;
+
+/// Everything that can go wrong while parsing or evaluating an expression.
+/// Every variant carries the span of the input it refers to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcError {
+    /// A word that should have been a number, e.g. `abc` or `1.2.3`.
+    ParseNumber { text: String, span: Span },
+    /// A symbol that is not a supported operator, e.g. `&` or `x`.
+    UnknownOperator { operator: String, span: Span },
+    /// The right operand of `/`, `//`, `%` or `mod` evaluated to zero.
+    DivisionByZero { span: Span },
+    /// A token appeared where something else was expected.
+    UnexpectedToken {
+        found: String,
+        expected: &'static str,
+        span: Span,
+    },
+    /// The input stopped where an operand was still expected.
+    UnexpectedEnd { span: Span },
+    /// A `(` without its closing `)`.
+    UnclosedParen { span: Span },
+    /// A `)` without an opening `(`.
+    UnmatchedParen { span: Span },
+    /// Finite operands produced a result too large for `f64`.
+    Overflow { span: Span },
+    /// A call to a function that does not exist. `suggestion` is the closest
+    /// known name, if any is close enough to be a likely typo.
+    UnknownFunction {
+        name: String,
+        suggestion: Option<String>,
+        span: Span,
+    },
+    /// A function was called with the wrong number of arguments.
+    WrongArity {
+        name: String,
+        expected: Arity,
+        found: usize,
+        span: Span,
+    },
+    /// A name that is neither a variable nor a constant.
+    UnknownVariable {
+        name: String,
+        suggestion: Option<String>,
+        span: Span,
+    },
+    /// `ans`, `_` or `$n` referred to a result that does not exist yet.
+    /// `count` is how many results there are so far.
+    NoSuchResult {
+        reference: String,
+        count: usize,
+        span: Span,
+    },
+    /// An assignment to `ans` or `_`, which always mean the last result.
+    AssignToReserved { name: String, span: Span },
+    /// A function definition that reuses the name of a built-in function.
+    RedefineBuiltin { name: String, span: Span },
+    /// A function definition listing the same parameter twice.
+    DuplicateParameter { name: String, span: Span },
+    /// User-defined functions called each other more than `limit` levels deep.
+    RecursionLimit {
+        name: String,
+        limit: usize,
+        span: Span,
+    },
+    /// An error inside the body of the user-defined function `name`. The
+    /// span is that of the call, since the body was typed on another line.
+    InFunction {
+        name: String,
+        error: Box<CalcError>,
+        span: Span,
+    },
+    /// An assignment to the name of a constant, e.g. `pi = 3`.
+    AssignToConstant { name: String, span: Span },
+    /// A physical constant was used before being enabled.
+    ConstantNotEnabled { name: String, span: Span },
+    /// The arguments are outside the function's domain, e.g. `sqrt(-1)`.
+    Domain {
+        function: String,
+        message: String,
+        span: Span,
+    },
+    /// A division in int mode that leaves a remainder, e.g. `7 / 2`.
+    InexactDivision { span: Span },
+    /// Quantities that measure different things were added, subtracted or
+    /// converted, e.g. `5 m + 3 s`. An empty unit is a plain number.
+    IncompatibleUnits {
+        left: String,
+        right: String,
+        span: Span,
+    },
+    /// A conversion to a name that is not a unit, e.g. `5 m to meters`.
+    UnknownUnit {
+        name: String,
+        suggestion: Option<String>,
+        span: Span,
+    },
+}
+
+impl CalcError {
+    pub fn span(&self) -> Span {
+        match self {
+            CalcError::ParseNumber { span, .. }
+            | CalcError::UnknownOperator { span, .. }
+            | CalcError::DivisionByZero { span }
+            | CalcError::UnexpectedToken { span, .. }
+            | CalcError::UnexpectedEnd { span }
+            | CalcError::UnclosedParen { span }
+            | CalcError::UnmatchedParen { span }
+            | CalcError::Overflow { span }
+            | CalcError::InexactDivision { span }
+            | CalcError::UnknownFunction { span, .. }
+            | CalcError::WrongArity { span, .. }
+            | CalcError::Domain { span, .. }
+            | CalcError::ConstantNotEnabled { span, .. }
+            | CalcError::UnknownVariable { span, .. }
+            | CalcError::AssignToConstant { span, .. }
+            | CalcError::NoSuchResult { span, .. }
+            | CalcError::AssignToReserved { span, .. }
+            | CalcError::RedefineBuiltin { span, .. }
+            | CalcError::DuplicateParameter { span, .. }
+            | CalcError::RecursionLimit { span, .. }
+            | CalcError::IncompatibleUnits { span, .. }
+            | CalcError::UnknownUnit { span, .. }
+            | CalcError::InFunction { span, .. } => *span,
+        }
+    }
+
+    /// A stable code identifying the kind of error, e.g. `E0003`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CalcError::ParseNumber { .. } => "E0001",
+            CalcError::UnknownOperator { .. } => "E0002",
+            CalcError::DivisionByZero { .. } => "E0003",
+            CalcError::UnexpectedToken { .. } => "E0004",
+            CalcError::UnclosedParen { .. } => "E0005",
+            CalcError::UnexpectedEnd { .. } => "E0006",
+            CalcError::UnmatchedParen { .. } => "E0007",
+            CalcError::Overflow { .. } => "E0008",
+            CalcError::UnknownFunction { .. } => "E0009",
+            CalcError::WrongArity { .. } => "E0010",
+            CalcError::Domain { .. } => "E0011",
+            CalcError::ConstantNotEnabled { .. } => "E0012",
+            CalcError::UnknownVariable { .. } => "E0013",
+            CalcError::AssignToConstant { .. } => "E0014",
+            CalcError::NoSuchResult { .. } => "E0015",
+            CalcError::AssignToReserved { .. } => "E0016",
+            CalcError::RedefineBuiltin { .. } => "E0017",
+            CalcError::DuplicateParameter { .. } => "E0018",
+            CalcError::RecursionLimit { .. } => "E0019",
+            CalcError::InexactDivision { .. } => "E0020",
+            CalcError::IncompatibleUnits { .. } => "E0021",
+            CalcError::UnknownUnit { .. } => "E0022",
+            CalcError::InFunction { error, .. } => error.code(),
+        }
+    }
+
+    /// The variant name in snake_case, e.g. `division_by_zero`, for tools
+    /// that would rather not match on codes. Like `code`, errors inside a
+    /// function report the kind of the underlying error.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CalcError::ParseNumber { .. } => "parse_number",
+            CalcError::UnknownOperator { .. } => "unknown_operator",
+            CalcError::DivisionByZero { .. } => "division_by_zero",
+            CalcError::UnexpectedToken { .. } => "unexpected_token",
+            CalcError::UnclosedParen { .. } => "unclosed_paren",
+            CalcError::UnexpectedEnd { .. } => "unexpected_end",
+            CalcError::UnmatchedParen { .. } => "unmatched_paren",
+            CalcError::Overflow { .. } => "overflow",
+            CalcError::UnknownFunction { .. } => "unknown_function",
+            CalcError::WrongArity { .. } => "wrong_arity",
+            CalcError::Domain { .. } => "domain",
+            CalcError::ConstantNotEnabled { .. } => "constant_not_enabled",
+            CalcError::UnknownVariable { .. } => "unknown_variable",
+            CalcError::AssignToConstant { .. } => "assign_to_constant",
+            CalcError::NoSuchResult { .. } => "no_such_result",
+            CalcError::AssignToReserved { .. } => "assign_to_reserved",
+            CalcError::RedefineBuiltin { .. } => "redefine_builtin",
+            CalcError::DuplicateParameter { .. } => "duplicate_parameter",
+            CalcError::RecursionLimit { .. } => "recursion_limit",
+            CalcError::InexactDivision { .. } => "inexact_division",
+            CalcError::IncompatibleUnits { .. } => "incompatible_units",
+            CalcError::UnknownUnit { .. } => "unknown_unit",
+            CalcError::InFunction { error, .. } => error.kind(),
+        }
+    }
+
+    /// Whether the input ended while more was expected, like `(1 +`, rather
+    /// than containing a mistake. Appending more input could fix it.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(
+            self,
+            CalcError::UnexpectedEnd { .. } | CalcError::UnclosedParen { .. }
+        )
+    }
+
+    /// A suggestion for fixing the input, if there is an obvious one.
+    pub fn help(&self) -> Option<String> {
+        match self {
+            CalcError::UnknownOperator { operator, .. } => suggest_operator(operator)
+                .map(|suggestion| format!("did you mean `{}`?", suggestion)),
+            CalcError::UnknownFunction {
+                suggestion: Some(suggestion),
+                ..
+            }
+            | CalcError::UnknownVariable {
+                suggestion: Some(suggestion),
+                ..
+            }
+            | CalcError::UnknownUnit {
+                suggestion: Some(suggestion),
+                ..
+            } => Some(format!("did you mean `{}`?", suggestion)),
+            CalcError::AssignToConstant { .. }
+            | CalcError::AssignToReserved { .. }
+            | CalcError::RedefineBuiltin { .. } => Some("choose a different name".to_string()),
+            CalcError::RecursionLimit { .. } => {
+                Some("recursive functions need an `if` that stops calling themselves".to_string())
+            }
+            CalcError::InFunction { error, .. } => error.help(),
+            CalcError::NoSuchResult { count: 0, .. } => {
+                Some("calculate something first, then refer back to it".to_string())
+            }
+            CalcError::NoSuchResult { count, .. } => {
+                Some(format!("results so far are numbered $1 to ${}", count))
+            }
+            CalcError::ConstantNotEnabled { .. } => {
+                Some("physical constants are opt-in; enable them with `:physics on`".to_string())
+            }
+            CalcError::InexactDivision { .. } => {
+                Some("use `//` to round down, or `:mode float` to allow fractions".to_string())
+            }
+            CalcError::IncompatibleUnits { .. } => {
+                Some("both sides need the same kind of unit, such as two lengths".to_string())
+            }
+            CalcError::UnexpectedToken { found, .. }
+                if units::is_unit(found.trim_matches('\'')) =>
+            {
+                Some(
+                    "units go right after a number; multiply other values, like `x * 1 m`"
+                        .to_string(),
+                )
+            }
+            CalcError::UnexpectedEnd { .. } => Some("add a number or '(' here".to_string()),
+            CalcError::UnclosedParen { .. } => {
+                Some("this '(' is never closed; add a ')' at the end".to_string())
+            }
+            CalcError::UnmatchedParen { .. } => {
+                Some("remove this ')' or add a '(' before it".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::new(self.code(), self.to_string(), self.span());
+        match self.help() {
+            Some(help) => diagnostic.with_help(help),
+            None => diagnostic,
+        }
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CalcError::ParseNumber { text, .. } => write!(f, "'{}' is not a valid number", text),
+            CalcError::UnknownOperator { operator, .. } => {
+                write!(f, "Unsupported operator: {}", operator)
+            }
+            CalcError::DivisionByZero { .. } => write!(f, "Cannot divide by zero!"),
+            CalcError::UnexpectedToken {
+                found, expected, ..
+            } => write!(f, "Expected {} but found {}", expected, found),
+            CalcError::UnexpectedEnd { .. } => {
+                write!(f, "Format should be: number operator number")
+            }
+            CalcError::UnclosedParen { .. } => write!(f, "Missing closing ')'"),
+            CalcError::UnmatchedParen { .. } => {
+                write!(f, "Unexpected ')' without a matching '('")
+            }
+            CalcError::Overflow { .. } => write!(f, "Result is too large to represent"),
+            CalcError::InexactDivision { .. } => {
+                write!(f, "Division leaves a remainder in int mode")
+            }
+            CalcError::UnknownFunction { name, .. } => write!(f, "Unknown function '{}'", name),
+            CalcError::WrongArity {
+                name,
+                expected,
+                found,
+                ..
+            } => write!(f, "{} expects {} but got {}", name, expected, found),
+            CalcError::Domain { message, .. } => write!(f, "Domain error: {}", message),
+            CalcError::UnknownVariable { name, .. } => write!(f, "Unknown variable '{}'", name),
+            CalcError::IncompatibleUnits { left, right, .. } => {
+                let describe = |unit: &str| {
+                    if unit.is_empty() {
+                        "a plain number".to_string()
+                    } else {
+                        format!("'{}'", unit)
+                    }
+                };
+                write!(
+                    f,
+                    "Incompatible units: {} and {}",
+                    describe(left),
+                    describe(right)
+                )
+            }
+            CalcError::UnknownUnit { name, .. } => write!(f, "Unknown unit '{}'", name),
+            CalcError::AssignToConstant { name, .. } => {
+                write!(f, "Cannot assign to constant '{}'", name)
+            }
+            CalcError::NoSuchResult { reference, .. } => {
+                write!(f, "There is no result for '{}'", reference)
+            }
+            CalcError::RedefineBuiltin { name, .. } => {
+                write!(f, "Cannot redefine built-in function '{}'", name)
+            }
+            CalcError::DuplicateParameter { name, .. } => {
+                write!(f, "Parameter '{}' is listed more than once", name)
+            }
+            CalcError::RecursionLimit { name, limit, .. } => write!(
+                f,
+                "Calling '{}' nested more than {} function calls deep",
+                name, limit
+            ),
+            CalcError::InFunction { name, error, .. } => write!(f, "In '{}': {}", name, error),
+            CalcError::AssignToReserved { name, .. } => {
+                write!(
+                    f,
+                    "Cannot assign to '{}'; it always means the last result",
+                    name
+                )
+            }
+            CalcError::ConstantNotEnabled { name, .. } => {
+                write!(f, "'{}' is a physical constant that is not enabled", name)
+            }
+        }
+    }
+}
+
+impl Error for CalcError {}
+
+/// Suggests the operator a user probably meant when typing `text`.
+fn suggest_operator(text: &str) -> Option<&'static str> {
+    match text {
+        "x" | "X" | "×" | "·" => Some("*"),
+        "÷" | ":" => Some("/"),
+        "−" | "–" => Some("-"),
+        _ => None,
+    }
+}
+
+/// The candidate closest to `name` by edit distance, if it is close enough
+/// to be a plausible typo.
+pub(crate) fn closest_match<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Edit distance between `a` and `b`, counted in characters. Swapping two
+/// neighbouring characters counts as one edit, since that is a common typo.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // distances[i][j] is the distance between the first i chars of a and the first j of b
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in distances[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut best = substitution
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = best;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+/// Whether `text` looks like a mistyped operator rather than a misplaced name.
+pub fn is_operator_typo(text: &str) -> bool {
+    suggest_operator(text).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostic_includes_code_and_help() {
+        let error = CalcError::UnknownOperator {
+            operator: "x".to_string(),
+            span: Span::new(2, 3),
+        };
+        let diagnostic = error.diagnostic();
+        assert_eq!(diagnostic.code, "E0002");
+        assert_eq!(error.kind(), "unknown_operator");
+        assert_eq!(diagnostic.message, "Unsupported operator: x");
+        assert_eq!(diagnostic.help.as_deref(), Some("did you mean `*`?"));
+    }
+
+    #[test]
+    fn test_closest_match() {
+        let names = ["sqrt", "sin", "sinh", "cos"];
+        assert_eq!(closest_match("sqr", names), Some("sqrt"));
+        assert_eq!(closest_match("cso", names), Some("cos"));
+        assert_eq!(closest_match("xyz", names), None);
+        assert_eq!(closest_match("sinn", names), Some("sin"));
+        assert_eq!(closest_match("tan", names), None);
+    }
+
+    #[test]
+    fn test_incompatible_units() {
+        let error = CalcError::IncompatibleUnits {
+            left: "km".to_string(),
+            right: String::new(),
+            span: Span::new(0, 8),
+        };
+        assert_eq!(error.code(), "E0021");
+        assert_eq!(
+            error.to_string(),
+            "Incompatible units: 'km' and a plain number"
+        );
+        assert!(error.help().unwrap().contains("same kind of unit"));
+    }
+
+    #[test]
+    fn test_display_matches_legacy_messages() {
+        let span = Span::new(0, 1);
+        assert_eq!(
+            CalcError::DivisionByZero { span }.to_string(),
+            "Cannot divide by zero!"
+        );
+        assert_eq!(
+            CalcError::ParseNumber {
+                text: "abc".to_string(),
+                span
+            }
+            .to_string(),
+            "'abc' is not a valid number"
+        );
+    }
+}
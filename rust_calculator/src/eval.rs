@@ -0,0 +1,1543 @@
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+use crate::ast::{BinaryOp, Expr, Statement, UnaryOp};
+use crate::complex;
+use crate::constants;
+use crate::decimal;
+use crate::env::{Environment, UserFunction, LAST_RESULT_NAMES};
+use crate::error::{closest_match, CalcError};
+use crate::functions::{self, Arity, FUNCTIONS};
+use crate::integer;
+use crate::lexer::Span;
+use crate::number::{Mode, Number};
+use crate::rational;
+use crate::units::{Dimension, Quantity, Unit};
+
+/// How deeply user-defined functions may call each other before evaluation
+/// gives up, so runaway recursion reports an error instead of overflowing the stack.
+pub const MAX_CALL_DEPTH: usize = 200;
+
+/// Floats up to this size that are whole are integers in int mode. Larger
+/// ones may already have lost digits.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// `if(condition, then, otherwise)` is built into the evaluator rather than
+/// the function table, because only the chosen branch may be evaluated.
+pub(crate) const IF: &str = "if";
+
+/// Evaluates a parsed expression with the default [`Environment`] and
+/// returns the result as `f64`. Whole results too large for `f64` are an
+/// overflow, and complex results are NaN. Quantities are given in SI base
+/// units, since an `f64` has no room for the unit: `5 km + 300 m` is `5300`
+/// and `1 h` is `3600`. An expression that ends in a conversion gives the
+/// unit it asks for, so `60 mph to km/h` is `96.56064`.
+pub fn eval(expr: &Expr) -> Result<f64, CalcError> {
+    let number = eval_with(expr, &Environment::new())?;
+    let value = match &number {
+        Number::Quantity(quantity) if matches!(expr, Expr::Convert { .. }) => quantity.value,
+        Number::Quantity(quantity) => quantity.value * quantity.unit.scale(),
+        number => number.to_f64(),
+    };
+    if value.is_infinite() && !matches!(number, Number::Float(_)) {
+        return Err(CalcError::Overflow { span: expr.span() });
+    }
+    Ok(value)
+}
+
+/// Runs a statement against `env`, storing assigned variables and defined
+/// functions in it. Returns the value of the expression or of the assigned
+/// variable; function definitions have no value.
+pub fn exec_with(
+    statement: &Statement,
+    env: &mut Environment,
+) -> Result<Option<Number>, CalcError> {
+    match statement {
+        Statement::Expr(expr) => eval_with(expr, env).map(Some),
+        Statement::Assign {
+            name,
+            name_span,
+            value,
+        } => {
+            if LAST_RESULT_NAMES.contains(&name.as_str()) {
+                return Err(CalcError::AssignToReserved {
+                    name: name.clone(),
+                    span: *name_span,
+                });
+            }
+            if env.constant(name).is_some() {
+                return Err(CalcError::AssignToConstant {
+                    name: name.clone(),
+                    span: *name_span,
+                });
+            }
+            let value = eval_with(value, env)?;
+            env.set_variable(name, value.clone());
+            Ok(Some(value))
+        }
+        Statement::Define {
+            name,
+            name_span,
+            params,
+            body,
+        } => {
+            if name == IF || functions::lookup(name).is_some() {
+                return Err(CalcError::RedefineBuiltin {
+                    name: name.clone(),
+                    span: *name_span,
+                });
+            }
+            for (i, (param, span)) in params.iter().enumerate() {
+                if params[..i].iter().any(|(other, _)| other == param) {
+                    return Err(CalcError::DuplicateParameter {
+                        name: param.clone(),
+                        span: *span,
+                    });
+                }
+            }
+            // Names in the body are resolved when the function is called, so
+            // it may refer to itself or to variables defined later
+            env.define_function(UserFunction {
+                name: name.clone(),
+                params: params.iter().map(|(param, _)| param.clone()).collect(),
+                body: body.clone(),
+            });
+            Ok(None)
+        }
+    }
+}
+
+/// Evaluates a parsed expression, resolving names through `env` and
+/// calculating with the kind of number its mode selects.
+pub fn eval_with(expr: &Expr, env: &Environment) -> Result<Number, CalcError> {
+    Scope {
+        env,
+        locals: Vec::new(),
+        depth: 0,
+    }
+    .eval(expr)
+}
+
+/// Where an expression is being evaluated: at the top level, or inside the
+/// body of a user-defined function with its parameters bound.
+struct Scope<'a> {
+    env: &'a Environment,
+    /// Parameters of the function being evaluated. Functions only see their
+    /// own parameters, never those of their caller.
+    locals: Vec<(&'a str, Number)>,
+    /// Number of user-defined function calls this scope is nested in.
+    depth: usize,
+}
+
+impl<'a> Scope<'a> {
+    fn eval(&self, expr: &Expr) -> Result<Number, CalcError> {
+        let env = self.env;
+        match expr {
+            Expr::Number { value, text, .. } => Ok(self.literal(*value, text)),
+            Expr::Imaginary { value, .. } => {
+                Ok(self.convert(Number::Complex(Complex64::new(0.0, *value))))
+            }
+            Expr::Variable { name, span } => self.variable(name, *span),
+            Expr::ResultRef { index, span } => match env.result(*index) {
+                Some(result) => Ok(self.convert(result.clone())),
+                None => Err(CalcError::NoSuchResult {
+                    reference: format!("${}", index),
+                    count: env.results().len(),
+                    span: *span,
+                }),
+            },
+            Expr::Unary { op, operand, span } => {
+                let value = self.eval(operand)?;
+                Ok(match (op, value) {
+                    (UnaryOp::Neg, Number::Float(value)) => Number::Float(-value),
+                    (UnaryOp::Neg, Number::Integer(value)) => Number::Integer(-value),
+                    (UnaryOp::Neg, Number::Decimal(value)) => Number::Decimal(-value),
+                    (UnaryOp::Neg, Number::Rational(value)) => Number::Rational(-value),
+                    (UnaryOp::Neg, Number::Complex(value)) => Number::Complex(-value),
+                    (UnaryOp::Neg, Number::Quantity(value)) => {
+                        Number::Quantity(Quantity::new(-value.value, value.unit))
+                    }
+                    (UnaryOp::Plus, value) => value,
+                    (UnaryOp::Factorial, value) => self.factorial(&value, *span)?,
+                })
+            }
+            Expr::Binary {
+                op,
+                left,
+                right,
+                span,
+            } => {
+                let num1 = self.eval(left)?;
+                let num2 = self.eval(right)?;
+                match (num1, num2) {
+                    (Number::Integer(num1), Number::Integer(num2)) => {
+                        self.integer_binary(*op, &num1, &num2, right.span(), *span)
+                    }
+                    (Number::Decimal(num1), Number::Decimal(num2)) => {
+                        self.decimal_binary(*op, &num1, &num2, right.span(), *span)
+                    }
+                    (Number::Rational(num1), Number::Rational(num2)) => {
+                        self.rational_binary(*op, &num1, &num2, right.span(), *span)
+                    }
+                    (num1 @ Number::Quantity(_), num2) | (num1, num2 @ Number::Quantity(_)) => {
+                        self.quantity_binary(*op, num1, num2, right.span(), *span)
+                    }
+                    (num1, num2) if self.is_complex_binary(*op, &num1, &num2) => self
+                        .complex_binary(
+                            *op,
+                            num1.to_complex(),
+                            num2.to_complex(),
+                            right.span(),
+                            *span,
+                        ),
+                    // Infinity and NaN are floats in every mode, and so are
+                    // results like `sqrt(2)` in rational mode
+                    (num1, num2) => {
+                        float_binary(*op, num1.to_f64(), num2.to_f64(), right.span(), *span)
+                            .map(|result| self.convert(Number::Float(result)))
+                    }
+                }
+            }
+            Expr::Call { name, args, span } => self.call(name, args, *span),
+            Expr::Quantity { value, unit, span } => self.with_unit(value, unit, *span),
+            Expr::Convert { value, unit, span } => self.convert_unit(value, unit, *span),
+        }
+    }
+
+    /// `value unit`, such as `5 km`.
+    fn with_unit(&self, value: &Expr, unit: &Unit, span: Span) -> Result<Number, CalcError> {
+        let value = to_quantity(self.eval(value)?, span)?;
+        let (unit, multiplier) = value
+            .unit
+            .multiply(unit)
+            .map_err(|_| CalcError::Overflow { span })?;
+        Ok(self.quantity(value.value * multiplier, unit))
+    }
+
+    /// `value to unit`. A plain number only converts to units without a
+    /// dimension, like `pi to deg`.
+    fn convert_unit(&self, value: &Expr, unit: &Unit, span: Span) -> Result<Number, CalcError> {
+        let value = to_quantity(self.eval(value)?, span)?;
+        match value.convert(unit) {
+            Some(result) => Ok(self.quantity(result.value, result.unit)),
+            None => Err(incompatible(&value.unit, unit, span)),
+        }
+    }
+
+    /// `a op b` where either side has a unit. Sums, remainders and floor
+    /// divisions need the same dimension on both sides, and sums give their
+    /// result in the left unit: `5 km + 300 m` is `5.3 km`.
+    fn quantity_binary(
+        &self,
+        op: BinaryOp,
+        num1: Number,
+        num2: Number,
+        right: Span,
+        span: Span,
+    ) -> Result<Number, CalcError> {
+        let (num1, num2) = (to_quantity(num1, span)?, to_quantity(num2, right)?);
+        match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::FloorDiv | BinaryOp::Rem | BinaryOp::Mod => {
+                let Some(factor) = num2.unit.conversion(&num1.unit) else {
+                    return Err(incompatible(&num1.unit, &num2.unit, span));
+                };
+                let result = float_binary(op, num1.value, num2.value * factor, right, span)?;
+                if op == BinaryOp::FloorDiv {
+                    // How many times the right side fits into the left
+                    Ok(self.convert(Number::Float(result)))
+                } else {
+                    Ok(self.quantity(result, num1.unit))
+                }
+            }
+            BinaryOp::Mul | BinaryOp::Div => {
+                let other = if op == BinaryOp::Div {
+                    num2.unit.pow(-1)
+                } else {
+                    Ok(num2.unit)
+                };
+                let (unit, multiplier) = other
+                    .and_then(|other| num1.unit.multiply(&other))
+                    .map_err(|_| CalcError::Overflow { span })?;
+                let result = float_binary(op, num1.value, num2.value, right, span)?;
+                Ok(self.quantity(result * multiplier, unit))
+            }
+            BinaryOp::Pow => {
+                if !num2.unit.dimension().is_ok_and(Dimension::is_none) {
+                    return Err(CalcError::Domain {
+                        function: op.symbol().to_string(),
+                        message: "powers must be numbers without units".to_string(),
+                        span: right,
+                    });
+                }
+                let exponent = num2.value * num2.unit.scale();
+                let unit = num1
+                    .unit
+                    .root_or_pow(exponent)
+                    .map_err(|_| CalcError::Overflow { span })?;
+                let Some(unit) = unit else {
+                    return Err(CalcError::Domain {
+                        function: op.symbol().to_string(),
+                        message: format!("{} to the power {} is not a unit", num1.unit, exponent),
+                        span,
+                    });
+                };
+                let result = float_binary(op, num1.value, exponent, right, span)?;
+                Ok(self.quantity(result, unit))
+            }
+        }
+    }
+
+    /// A number with `unit`, or a plain number in the session's mode if the
+    /// unit has cancelled out.
+    fn quantity(&self, value: f64, unit: Unit) -> Number {
+        if unit.is_none() {
+            self.convert(Number::Float(value))
+        } else {
+            Number::Quantity(Quantity::new(value, unit))
+        }
+    }
+
+    /// `a op b` for two integers. Divisions that leave a remainder are
+    /// floats, or an error in int mode, and so are negative powers.
+    fn integer_binary(
+        &self,
+        op: BinaryOp,
+        num1: &BigInt,
+        num2: &BigInt,
+        right: Span,
+        span: Span,
+    ) -> Result<Number, CalcError> {
+        let result = match op {
+            BinaryOp::Add => num1 + num2,
+            BinaryOp::Sub => num1 - num2,
+            BinaryOp::Mul => num1 * num2,
+            BinaryOp::Div | BinaryOp::FloorDiv | BinaryOp::Rem | BinaryOp::Mod
+                if num2.is_zero() =>
+            {
+                return Err(CalcError::DivisionByZero { span: right });
+            }
+            BinaryOp::Div => {
+                let (quotient, remainder) = num1.div_rem(num2);
+                if remainder.is_zero() {
+                    quotient
+                } else if self.env.mode() == Mode::Integer {
+                    return Err(CalcError::InexactDivision { span });
+                } else {
+                    let fraction = BigRational::new(num1.clone(), num2.clone());
+                    return Ok(self.convert(Number::Float(rational::to_f64(&fraction))));
+                }
+            }
+            BinaryOp::FloorDiv => num1.div_floor(num2),
+            BinaryOp::Rem => num1 % num2,
+            BinaryOp::Mod => num1.mod_floor(&num2.abs()),
+            BinaryOp::Pow if num2.is_negative() => {
+                // `2 ^ -1` is `1 / 2` in disguise, so only 1 and -1 have
+                // whole negative powers
+                if num1.is_zero() {
+                    return Err(CalcError::DivisionByZero { span });
+                } else if num1.abs().is_one() {
+                    if num2.is_even() {
+                        BigInt::one()
+                    } else {
+                        num1.clone()
+                    }
+                } else if self.env.mode() == Mode::Integer {
+                    return Err(CalcError::InexactDivision { span });
+                } else {
+                    return self.float_fallback(op, num1, num2, right, span);
+                }
+            }
+            BinaryOp::Pow => match integer::pow(num1, num2) {
+                Some(result) => result,
+                // Enormous powers are left to `f64`, which overflows
+                None => return self.float_fallback(op, num1, num2, right, span),
+            },
+        };
+        Ok(Number::Integer(result))
+    }
+
+    /// Whether `a op b` is complex: when either operand is, or in complex
+    /// mode for a negative number to a fractional power.
+    fn is_complex_binary(&self, op: BinaryOp, num1: &Number, num2: &Number) -> bool {
+        match (num1, num2) {
+            (Number::Complex(_), _) | (_, Number::Complex(_)) => true,
+            (num1, num2) => {
+                self.env.mode() == Mode::Complex
+                    && op == BinaryOp::Pow
+                    && num1.to_f64() < 0.0
+                    && num2.to_f64().fract() != 0.0
+            }
+        }
+    }
+
+    /// `a op b` for complex numbers. Only the operators of real numbers
+    /// that have no rounding are defined for them.
+    fn complex_binary(
+        &self,
+        op: BinaryOp,
+        num1: Complex64,
+        num2: Complex64,
+        right: Span,
+        span: Span,
+    ) -> Result<Number, CalcError> {
+        let result = match op {
+            BinaryOp::Add => num1 + num2,
+            BinaryOp::Sub => num1 - num2,
+            BinaryOp::Mul => num1 * num2,
+            BinaryOp::Div if num2.is_zero() => {
+                return Err(CalcError::DivisionByZero { span: right });
+            }
+            BinaryOp::Div => num1 / num2,
+            BinaryOp::FloorDiv | BinaryOp::Rem | BinaryOp::Mod => {
+                return Err(CalcError::Domain {
+                    function: op.symbol().to_string(),
+                    message: format!("{} is not defined for complex numbers", op.symbol()),
+                    span,
+                });
+            }
+            BinaryOp::Pow => {
+                if num1.is_zero() && num2.re <= 0.0 {
+                    return Err(CalcError::DivisionByZero { span });
+                }
+                complex::pow(num1, num2)
+            }
+        };
+        if !result.is_finite() && num1.is_finite() && num2.is_finite() {
+            return Err(CalcError::Overflow { span });
+        }
+        Ok(self.convert(Number::Complex(result)))
+    }
+
+    /// `a op b` for two integers, calculated as `f64`.
+    fn float_fallback(
+        &self,
+        op: BinaryOp,
+        num1: &BigInt,
+        num2: &BigInt,
+        right: Span,
+        span: Span,
+    ) -> Result<Number, CalcError> {
+        let (num1, num2) = (integer::to_f64(num1), integer::to_f64(num2));
+        let result = float_binary(op, num1, num2, right, span)?;
+        Ok(self.convert(Number::Float(result)))
+    }
+
+    /// `n!` for a whole `n` of any kind.
+    fn factorial(&self, value: &Number, span: Span) -> Result<Number, CalcError> {
+        let n = match value.to_integer() {
+            Some(n) if !n.is_negative() => n,
+            _ => {
+                return Err(CalcError::Domain {
+                    function: "!".to_string(),
+                    message: "factorials are only defined for integers >= 0".to_string(),
+                    span,
+                })
+            }
+        };
+        match n.to_u64() {
+            Some(n) if n <= integer::MAX_FACTORIAL => {
+                Ok(self.convert(Number::Integer(integer::factorial(n))))
+            }
+            _ => Err(CalcError::Overflow { span }),
+        }
+    }
+
+    /// `a op b` in decimal mode.
+    fn decimal_binary(
+        &self,
+        op: BinaryOp,
+        num1: &BigDecimal,
+        num2: &BigDecimal,
+        right: Span,
+        span: Span,
+    ) -> Result<Number, CalcError> {
+        let settings = self.env.decimal();
+        let result = match op {
+            BinaryOp::Add => settings.round(&(num1 + num2)),
+            BinaryOp::Sub => settings.round(&(num1 - num2)),
+            BinaryOp::Mul => settings.round(&(num1 * num2)),
+            BinaryOp::Div | BinaryOp::FloorDiv | BinaryOp::Rem | BinaryOp::Mod
+                if num2.is_zero() =>
+            {
+                return Err(CalcError::DivisionByZero { span: right });
+            }
+            BinaryOp::Div => decimal::divide(num1, num2, &settings),
+            BinaryOp::FloorDiv => {
+                decimal::floor_divide(num1, num2, &settings).ok_or(CalcError::Overflow { span })?
+            }
+            BinaryOp::Rem => {
+                decimal::remainder(num1, num2, &settings).ok_or(CalcError::Overflow { span })?
+            }
+            BinaryOp::Mod => {
+                decimal::modulo(num1, num2, &settings).ok_or(CalcError::Overflow { span })?
+            }
+            BinaryOp::Pow => {
+                if num1.is_zero() && num2.is_negative() {
+                    return Err(CalcError::DivisionByZero { span });
+                }
+                match decimal::pow(num1, num2, &settings) {
+                    Some(result) => result,
+                    // Fractional and enormous powers are left to `f64`
+                    None => {
+                        let (num1, num2) = (decimal::to_f64(num1), decimal::to_f64(num2));
+                        let result = float_binary(op, num1, num2, right, span)?;
+                        return Ok(self.convert(Number::Float(result)));
+                    }
+                }
+            }
+        };
+        if !decimal::in_range(&result) {
+            return Err(CalcError::Overflow { span });
+        }
+        Ok(Number::Decimal(result))
+    }
+
+    /// `a op b` in rational mode.
+    fn rational_binary(
+        &self,
+        op: BinaryOp,
+        num1: &BigRational,
+        num2: &BigRational,
+        right: Span,
+        span: Span,
+    ) -> Result<Number, CalcError> {
+        let result = match op {
+            BinaryOp::Add => num1 + num2,
+            BinaryOp::Sub => num1 - num2,
+            BinaryOp::Mul => num1 * num2,
+            BinaryOp::Div | BinaryOp::FloorDiv | BinaryOp::Rem | BinaryOp::Mod
+                if num2.is_zero() =>
+            {
+                return Err(CalcError::DivisionByZero { span: right });
+            }
+            BinaryOp::Div => num1 / num2,
+            BinaryOp::FloorDiv => rational::floor_divide(num1, num2),
+            BinaryOp::Rem => rational::remainder(num1, num2),
+            BinaryOp::Mod => rational::modulo(num1, num2),
+            BinaryOp::Pow => {
+                if num1.is_zero() && num2.is_negative() {
+                    return Err(CalcError::DivisionByZero { span });
+                }
+                match rational::pow(num1, num2) {
+                    Some(result) => result,
+                    // Irrational and enormous powers are left to `f64`
+                    None => {
+                        let (num1, num2) = (rational::to_f64(num1), rational::to_f64(num2));
+                        return float_binary(op, num1, num2, right, span).map(Number::Float);
+                    }
+                }
+            }
+        };
+        Ok(Number::Rational(result))
+    }
+
+    /// A number literal in the session's mode. Decimals and fractions are
+    /// read from the literal's text, so no digits are lost to `f64` on the
+    /// way.
+    fn literal(&self, value: f64, text: &str) -> Number {
+        match self.env.mode() {
+            Mode::Float | Mode::Integer | Mode::Complex => match integer::parse(text) {
+                Some(value) => Number::Integer(value),
+                None => self.convert(Number::Float(value)),
+            },
+            Mode::Decimal => match decimal::parse(text) {
+                Some(value) => Number::Decimal(self.env.decimal().round(&value)),
+                None => self.convert(Number::Float(value)),
+            },
+            Mode::Rational => match rational::parse(text) {
+                Some(value) => Number::Rational(value),
+                None => Number::Float(value),
+            },
+        }
+    }
+
+    /// Converts a value of any kind to the kind the session's mode
+    /// calculates with, such as a variable stored before the mode changed.
+    /// Rational and int mode keep floats as they are, unless int mode can
+    /// hold them exactly: a fraction made from `sqrt(2)` would only look
+    /// exact. Complex numbers without an imaginary part become real.
+    fn convert(&self, number: Number) -> Number {
+        let settings = self.env.decimal();
+        match (self.env.mode(), number) {
+            (_, Number::Complex(value)) if value.im == 0.0 => self.convert(Number::Float(value.re)),
+            (Mode::Float | Mode::Complex, number @ (Number::Decimal(_) | Number::Rational(_))) => {
+                Number::Float(number.to_f64())
+            }
+            (Mode::Integer, Number::Float(value)) if value.abs() <= MAX_SAFE_INTEGER => {
+                match integer::from_f64(value) {
+                    Some(value) => Number::Integer(value),
+                    None => Number::Float(value),
+                }
+            }
+            (Mode::Integer, number @ (Number::Decimal(_) | Number::Rational(_))) => {
+                match number.to_integer() {
+                    Some(value) => Number::Integer(value),
+                    None => Number::Float(number.to_f64()),
+                }
+            }
+            (Mode::Decimal, Number::Integer(value)) => {
+                Number::Decimal(settings.round(&BigDecimal::from(value)))
+            }
+            (Mode::Rational, Number::Integer(value)) => {
+                Number::Rational(BigRational::from_integer(value))
+            }
+            (Mode::Decimal, Number::Float(value)) => match decimal::from_f64(value) {
+                Some(value) => Number::Decimal(settings.round(&value)),
+                None => Number::Float(value),
+            },
+            (Mode::Decimal, Number::Rational(value)) => {
+                Number::Decimal(rational::to_decimal(&value, &settings))
+            }
+            (Mode::Rational, Number::Decimal(value)) => match rational::from_decimal(&value) {
+                Some(value) => Number::Rational(value),
+                None => Number::Float(decimal::to_f64(&value)),
+            },
+            (_, number) => number,
+        }
+    }
+
+    /// Resolves a name: parameters first, then `ans`, variables, constants
+    /// and the imaginary unit.
+    fn variable(&self, name: &str, span: Span) -> Result<Number, CalcError> {
+        let env = self.env;
+        if let Some((_, value)) = self.locals.iter().find(|(local, _)| *local == name) {
+            return Ok(value.clone());
+        }
+        if LAST_RESULT_NAMES.contains(&name) {
+            return match env.last_result() {
+                Some(result) => Ok(self.convert(result.clone())),
+                None => Err(CalcError::NoSuchResult {
+                    reference: name.to_string(),
+                    count: 0,
+                    span,
+                }),
+            };
+        }
+        if let Some(value) = env.variable(name) {
+            return Ok(self.convert(value.clone()));
+        }
+        if let Some(constant) = env.constant(name) {
+            return Ok(self.convert(Number::Float(constant.value)));
+        }
+        if name == complex::UNIT {
+            return Ok(Number::Complex(Complex64::I));
+        }
+        if constants::lookup(name).is_some() {
+            return Err(CalcError::ConstantNotEnabled {
+                name: name.to_string(),
+                span,
+            });
+        }
+
+        let names = self
+            .locals
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(env.variables().map(|(name, _)| name))
+            .chain(env.constants().map(|constant| constant.name));
+        Err(CalcError::UnknownVariable {
+            name: name.to_string(),
+            suggestion: closest_match(name, names).map(str::to_string),
+            span,
+        })
+    }
+
+    fn call(&self, name: &str, args: &[Expr], span: Span) -> Result<Number, CalcError> {
+        if name == IF {
+            check_arity(name, Arity::Exact(3), args, span)?;
+            let condition = self.eval(&args[0])?;
+            // Zero is false and anything else, including NaN, is true
+            let branch = if !condition.is_zero() {
+                &args[1]
+            } else {
+                &args[2]
+            };
+            return self.eval(branch);
+        }
+
+        if let Some(function) = self.env.function(name) {
+            check_arity(name, Arity::Exact(function.params.len()), args, span)?;
+            if self.depth >= MAX_CALL_DEPTH {
+                return Err(CalcError::RecursionLimit {
+                    name: name.to_string(),
+                    limit: MAX_CALL_DEPTH,
+                    span,
+                });
+            }
+            let values = self.eval_args(args)?;
+            let body_scope = Scope {
+                env: self.env,
+                locals: function
+                    .params
+                    .iter()
+                    .map(String::as_str)
+                    .zip(values)
+                    .collect(),
+                depth: self.depth + 1,
+            };
+            let result = body_scope.eval(&function.body);
+            // Spans inside the body refer to the line that defined it, so
+            // errors are reported at the outermost call instead
+            return if self.depth == 0 {
+                result.map_err(|error| CalcError::InFunction {
+                    name: name.to_string(),
+                    error: Box::new(error),
+                    span,
+                })
+            } else {
+                result
+            };
+        }
+
+        let Some(function) = functions::lookup(name) else {
+            let names = FUNCTIONS
+                .iter()
+                .map(|function| function.name)
+                .chain(self.env.functions().map(|function| function.name.as_str()))
+                .chain([IF]);
+            return Err(CalcError::UnknownFunction {
+                name: name.to_string(),
+                suggestion: closest_match(name, names).map(str::to_string),
+                span,
+            });
+        };
+        check_arity(name, function.arity, args, span)?;
+        let values = self.eval_args(args)?;
+        if values
+            .iter()
+            .any(|value| matches!(value, Number::Quantity(_)))
+        {
+            return self.quantity_call(function, &values, span);
+        }
+        if values
+            .iter()
+            .any(|value| matches!(value, Number::Complex(_)))
+        {
+            return self.complex_call(name, &values, span);
+        }
+        if let Some(result) = self.exact_call(name, &values) {
+            return result.map_err(|message| CalcError::Domain {
+                function: name.to_string(),
+                message,
+                span,
+            });
+        }
+        let reals: Vec<f64> = values.iter().map(Number::to_f64).collect();
+        match function.call(&reals) {
+            Ok(result) => Ok(self.convert(Number::Float(result))),
+            Err(_) if self.env.mode() == Mode::Complex && complex::BEYOND_REALS.contains(&name) => {
+                self.complex_call(name, &values, span)
+            }
+            Err(message) => Err(CalcError::Domain {
+                function: name.to_string(),
+                message,
+                span,
+            }),
+        }
+    }
+
+    /// A built-in function with a quantity among its arguments. Rounding and
+    /// picking functions keep the unit of the first argument, roots take
+    /// the root of the unit, and all others need arguments without units.
+    fn quantity_call(
+        &self,
+        function: &functions::Function,
+        values: &[Number],
+        span: Span,
+    ) -> Result<Number, CalcError> {
+        let name = function.name;
+        let quantities = values
+            .iter()
+            .map(|value| to_quantity(value.clone(), span))
+            .collect::<Result<Vec<_>, _>>()?;
+        let domain = |message| CalcError::Domain {
+            function: name.to_string(),
+            message,
+            span,
+        };
+        let first = &quantities[0];
+        match name {
+            "abs" | "floor" | "ceil" | "round" | "trunc" | "min" | "max" | "hypot" => {
+                let mut reals = Vec::with_capacity(quantities.len());
+                for quantity in &quantities {
+                    let Some(factor) = quantity.unit.conversion(&first.unit) else {
+                        return Err(incompatible(&first.unit, &quantity.unit, span));
+                    };
+                    reals.push(quantity.value * factor);
+                }
+                let result = function.call(&reals).map_err(domain)?;
+                Ok(self.quantity(result, first.unit.clone()))
+            }
+            "sqrt" | "cbrt" => {
+                let exponent = if name == "sqrt" { 1.0 / 2.0 } else { 1.0 / 3.0 };
+                // Roots only make powers smaller
+                let Ok(Some(unit)) = first.unit.root_or_pow(exponent) else {
+                    return Err(domain(format!("{} of {} is not a unit", name, first.unit)));
+                };
+                let result = function.call(&[first.value]).map_err(domain)?;
+                Ok(self.quantity(result, unit))
+            }
+            _ => {
+                let mut reals = Vec::with_capacity(quantities.len());
+                for quantity in &quantities {
+                    if !quantity.unit.dimension().is_ok_and(Dimension::is_none) {
+                        return Err(domain(format!(
+                            "{} is only defined for numbers without units",
+                            name
+                        )));
+                    }
+                    reals.push(quantity.value * quantity.unit.scale());
+                }
+                let result = function.call(&reals).map_err(domain)?;
+                Ok(self.convert(Number::Float(result)))
+            }
+        }
+    }
+
+    /// The complex version of a built-in function.
+    fn complex_call(&self, name: &str, values: &[Number], span: Span) -> Result<Number, CalcError> {
+        let values: Vec<Complex64> = values.iter().map(Number::to_complex).collect();
+        match complex::call(name, &values) {
+            Some(Ok(result)) => Ok(self.convert(Number::Complex(result))),
+            Some(Err(message)) => Err(CalcError::Domain {
+                function: name.to_string(),
+                message,
+                span,
+            }),
+            None => Err(CalcError::Domain {
+                function: name.to_string(),
+                message: format!("{} is not defined for complex numbers", name),
+                span,
+            }),
+        }
+    }
+
+    /// The integer, decimal or rational version of a built-in function, when
+    /// every argument is of that kind and it has one for them.
+    fn exact_call(&self, name: &str, values: &[Number]) -> Option<Result<Number, String>> {
+        match values.first()? {
+            Number::Decimal(_) => {
+                let decimals: Option<Vec<BigDecimal>> = values
+                    .iter()
+                    .map(|value| match value {
+                        Number::Decimal(value) => Some(value.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                decimal::call(name, &decimals?, &self.env.decimal())
+                    .map(|value| Ok(Number::Decimal(value)))
+            }
+            Number::Rational(_) => {
+                let fractions: Option<Vec<BigRational>> = values
+                    .iter()
+                    .map(|value| match value {
+                        Number::Rational(value) => Some(value.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                rational::call(name, &fractions?).map(|value| Ok(Number::Rational(value)))
+            }
+            Number::Integer(_) => {
+                let integers: Option<Vec<BigInt>> = values
+                    .iter()
+                    .map(|value| match value {
+                        Number::Integer(value) => Some(value.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                integer::call(name, &integers?).map(|result| result.map(Number::Integer))
+            }
+            Number::Float(_) | Number::Complex(_) | Number::Quantity(_) => None,
+        }
+    }
+
+    fn eval_args(&self, args: &[Expr]) -> Result<Vec<Number>, CalcError> {
+        args.iter().map(|arg| self.eval(arg)).collect()
+    }
+}
+
+/// `number` as a quantity, which has no unit if it is a plain number.
+/// Units only go with real numbers.
+fn to_quantity(number: Number, span: Span) -> Result<Quantity, CalcError> {
+    match number {
+        Number::Quantity(quantity) => Ok(quantity),
+        Number::Complex(_) => Err(CalcError::Domain {
+            function: "units".to_string(),
+            message: "units need a real number".to_string(),
+            span,
+        }),
+        number => Ok(Quantity::new(number.to_f64(), Unit::default())),
+    }
+}
+
+fn incompatible(left: &Unit, right: &Unit, span: Span) -> CalcError {
+    CalcError::IncompatibleUnits {
+        left: left.to_string(),
+        right: right.to_string(),
+        span,
+    }
+}
+
+/// `num1 op num2` in `f64`.
+fn float_binary(
+    op: BinaryOp,
+    num1: f64,
+    num2: f64,
+    right: Span,
+    span: Span,
+) -> Result<f64, CalcError> {
+    let result = match op {
+        BinaryOp::Add => num1 + num2,
+        BinaryOp::Sub => num1 - num2,
+        BinaryOp::Mul => num1 * num2,
+        BinaryOp::Div | BinaryOp::FloorDiv | BinaryOp::Rem | BinaryOp::Mod if num2 == 0.0 => {
+            return Err(CalcError::DivisionByZero { span: right });
+        }
+        BinaryOp::Div => num1 / num2,
+        BinaryOp::FloorDiv => (num1 / num2).floor(),
+        BinaryOp::Rem => num1 % num2,
+        BinaryOp::Mod => num1.rem_euclid(num2),
+        BinaryOp::Pow => {
+            // A negative power of zero is a division by zero in disguise
+            if num1 == 0.0 && num2 < 0.0 {
+                return Err(CalcError::DivisionByZero { span });
+            }
+            let result = num1.powf(num2);
+            if result.is_nan() && !num1.is_nan() && !num2.is_nan() {
+                return Err(CalcError::Domain {
+                    function: "^".to_string(),
+                    message: "a negative number to a fractional power is not a real number"
+                        .to_string(),
+                    span,
+                });
+            }
+            result
+        }
+    };
+
+    // Infinity is only an error if it was not already one of the inputs
+    if result.is_infinite() && num1.is_finite() && num2.is_finite() {
+        return Err(CalcError::Overflow { span });
+    }
+    Ok(result)
+}
+
+fn check_arity(name: &str, expected: Arity, args: &[Expr], span: Span) -> Result<(), CalcError> {
+    if expected.accepts(args.len()) {
+        Ok(())
+    } else {
+        Err(CalcError::WrongArity {
+            name: name.to_string(),
+            expected,
+            found: args.len(),
+            span,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn test_eval_parsed_expression() {
+        let expr = parse("(1 + 2) * 3").unwrap();
+        assert_eq!(eval(&expr).unwrap(), 9.0);
+        // The same tree can be evaluated again
+        assert_eq!(eval(&expr).unwrap(), 9.0);
+    }
+
+    #[test]
+    fn test_eval_unknown_function() {
+        let expr = parse("frobnicate(1)").unwrap();
+        assert!(matches!(
+            eval(&expr),
+            Err(CalcError::UnknownFunction { name, suggestion: None, .. }) if name == "frobnicate"
+        ));
+
+        let error = eval(&parse("sqr(4)").unwrap()).unwrap_err();
+        assert_eq!(error.help().as_deref(), Some("did you mean `sqrt`?"));
+    }
+
+    #[test]
+    fn test_eval_constants() {
+        let value = |input: &str| eval(&parse(input).unwrap()).unwrap();
+        assert_eq!(value("pi"), std::f64::consts::PI);
+        assert_eq!(value("2 * pi"), value("tau"));
+        assert_eq!(value("ln(e)"), 1.0);
+        assert_eq!(value("-inf"), f64::NEG_INFINITY);
+        assert!(value("nan").is_nan());
+        assert!((value("phi ^ 2 - phi") - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_eval_physics_constants_are_opt_in() {
+        let expr = parse("c * 2").unwrap();
+        let error = eval(&expr).unwrap_err();
+        assert!(matches!(error, CalcError::ConstantNotEnabled { ref name, .. } if name == "c"));
+        assert!(error.help().is_some());
+
+        let mut env = Environment::new();
+        env.set_physics(true);
+        assert_eq!(eval_with(&expr, &env).unwrap(), 599_584_916.0.into());
+        assert!(eval_with(&parse("k_B * N_A").unwrap(), &env).is_ok());
+    }
+
+    #[test]
+    fn test_exec_assignments() {
+        let mut env = Environment::new();
+        let mut run = |input: &str| exec_with(&crate::parse_statement(input).unwrap(), &mut env);
+        assert_eq!(run("let rate = 0.07").unwrap(), Some(0.07.into()));
+        assert_eq!(run("total = 100 * (1 + rate)").unwrap(), Some(107.0.into()));
+        assert_eq!(run("rate = rate * 2").unwrap(), Some(0.14.into()));
+        assert_eq!(run("total - 7").unwrap(), Some(100.0.into()));
+        assert_eq!(env.variables().count(), 2);
+    }
+
+    #[test]
+    fn test_exec_unknown_variable() {
+        let mut env = Environment::new();
+        env.set_variable("rate", 0.07);
+        let error = eval_with(&parse("rat * 2").unwrap(), &env).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown variable 'rat'");
+        assert_eq!(error.help().as_deref(), Some("did you mean `rate`?"));
+        assert_eq!(error.span(), crate::Span::new(0, 3));
+
+        let error = eval_with(&parse("tua").unwrap(), &env).unwrap_err();
+        assert_eq!(error.help().as_deref(), Some("did you mean `tau`?"));
+    }
+
+    #[test]
+    fn test_eval_result_references() {
+        let mut env = Environment::new();
+        let value = |input: &str, env: &Environment| eval_with(&parse(input).unwrap(), env);
+        assert!(matches!(
+            value("ans", &env),
+            Err(CalcError::NoSuchResult { count: 0, .. })
+        ));
+
+        env.push_result(15.0);
+        env.push_result(4.0);
+        assert_eq!(value("ans * 1.2", &env).unwrap(), 4.8.into());
+        assert_eq!(value("_ + 1", &env).unwrap(), 5.0.into());
+        assert_eq!(value("$1 - $2", &env).unwrap(), 11.0.into());
+
+        let error = value("$3", &env).unwrap_err();
+        assert!(matches!(error, CalcError::NoSuchResult { count: 2, .. }));
+        assert_eq!(
+            error.help().as_deref(),
+            Some("results so far are numbered $1 to $2")
+        );
+        assert!(value("$0", &env).is_err());
+    }
+
+    #[test]
+    fn test_exec_cannot_assign_last_result() {
+        let mut env = Environment::new();
+        for input in ["ans = 1", "let _ = 1"] {
+            let statement = crate::parse_statement(input).unwrap();
+            assert!(matches!(
+                exec_with(&statement, &mut env),
+                Err(CalcError::AssignToReserved { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_exec_cannot_assign_constants() {
+        let mut env = Environment::new();
+        let statement = crate::parse_statement("let pi = 3").unwrap();
+        assert!(matches!(
+            exec_with(&statement, &mut env),
+            Err(CalcError::AssignToConstant { ref name, .. }) if name == "pi"
+        ));
+        // Physical constants only count once they are enabled
+        let statement = crate::parse_statement("c = 3").unwrap();
+        assert_eq!(exec_with(&statement, &mut env).unwrap(), Some(3.into()));
+        env.set_physics(true);
+        assert_eq!(eval_with(&parse("c").unwrap(), &env).unwrap(), 3.into());
+        assert!(exec_with(&crate::parse_statement("h = 1").unwrap(), &mut env).is_err());
+    }
+
+    #[test]
+    fn test_eval_decimal_mode() {
+        let mut env = Environment::new();
+        env.set_mode(Mode::Decimal);
+        let value = |input: &str, env: &Environment| {
+            eval_with(&parse(input).unwrap(), env).unwrap().to_string()
+        };
+        assert_eq!(value("0.1 + 0.2", &env), "0.3");
+        assert_eq!(value("1 - 0.9", &env), "0.1");
+        assert_eq!(value("19.99 * 3", &env), "59.97");
+        assert_eq!(value("10 / 4 // 1 + 7 % 4 + -7 mod 3", &env), "7");
+        assert_eq!(value("1.1 ^ 2 - 2 ^ -1", &env), "0.71");
+        assert_eq!(
+            value("99999999999999999999 + 1", &env),
+            "100000000000000000000"
+        );
+        assert_eq!(value("round(2.675 * 100) / 100", &env), "2.68");
+        assert_eq!(value("if(0.1 + 0.2 - 0.3, 1, 2)", &env), "2");
+        // Without a decimal version, functions and powers go through f64
+        assert_eq!(value("sin(0)", &env), "0");
+        assert_eq!(value("4 ^ 0.5", &env), "2");
+        assert_eq!(value("-inf", &env), "-inf");
+        assert!(matches!(
+            eval_with(&parse("1 / (0.5 - 0.5)").unwrap(), &env),
+            Err(CalcError::DivisionByZero { .. })
+        ));
+        assert!(matches!(
+            eval_with(&parse("sqrt(-0.1)").unwrap(), &env),
+            Err(CalcError::Domain { .. })
+        ));
+        // Exponents too large to line up digit by digit are read as f64
+        assert_eq!(value("1e999999999 + 1", &env), "inf");
+        assert_eq!(value("1e99999999 // 7", &env), "inf");
+        assert_eq!(value("1e-5000000 + 1", &env), "1");
+        assert_eq!(value("1e99999 % 7", &env), "6");
+        assert!(matches!(
+            eval_with(&parse("1e60000 * 1e60000").unwrap(), &env),
+            Err(CalcError::Overflow { .. })
+        ));
+
+        env.set_decimal(env.decimal().with_precision(5).unwrap());
+        assert_eq!(value("2 / 3", &env), "0.66667");
+        assert_eq!(value("123456789", &env), "123460000");
+    }
+
+    #[test]
+    fn test_eval_rational_mode() {
+        let mut env = Environment::new();
+        env.set_mode(Mode::Rational);
+        let value = |input: &str, env: &Environment| {
+            eval_with(&parse(input).unwrap(), env).unwrap().to_string()
+        };
+        assert_eq!(value("1/3 + 1/6", &env), "1/2");
+        assert_eq!(value("0.1 + 0.2", &env), "3/10");
+        assert_eq!(value("(2/3) ^ -2 - 1/4", &env), "2");
+        assert_eq!(value("-7/2 // 1 + 7/2 % 1/3 + -1 mod 3", &env), "-11/6");
+        assert_eq!(
+            value("(4/9) ^ (3/2) + sqrt(1/4) + abs(-1/3)", &env),
+            "61/54"
+        );
+        assert_eq!(value("2 ^ 100", &env), "1267650600228229401496703205376");
+        assert_eq!(value("if(1/3 - 2/6, 1, 2)", &env), "2");
+        // Results that are not rational are floats, and stay floats
+        assert_eq!(value("sqrt(2)", &env), "1.4142135623730951");
+        assert_eq!(value("2 ^ (1/2) * 0", &env), "0");
+        assert!(matches!(
+            eval_with(&parse("sqrt(2) + 1/3").unwrap(), &env),
+            Ok(Number::Float(_))
+        ));
+        assert!(matches!(
+            eval_with(&parse("1 / (1/2 - 2/4)").unwrap(), &env),
+            Err(CalcError::DivisionByZero { .. })
+        ));
+        assert!(matches!(
+            eval_with(&parse("(-1/4) ^ (1/2)").unwrap(), &env),
+            Err(CalcError::Domain { .. })
+        ));
+    }
+
+    #[test]
+    fn test_eval_integers() {
+        let env = Environment::new();
+        let value = |input: &str| eval_with(&parse(input).unwrap(), &env).unwrap();
+        assert_eq!(
+            value("2^200").to_string(),
+            "1606938044258990275541962092341162602522202993782792835301376"
+        );
+        assert_eq!(
+            value("30!").to_string(),
+            "265252859812191058636308480000000"
+        );
+        assert_eq!(value("30! / 29!"), 30.into());
+        assert_eq!(value("-7 // 2 + -7 % 3 + -7 mod 3"), (-3).into());
+        assert_eq!(
+            value("gcd(2^100, 6^50) + lcm(4, 6)"),
+            Number::from(BigInt::from(2).pow(50) + 12)
+        );
+        assert_eq!(value("powmod(3, 10^18, 1000000007)"), 246_336_683.into());
+        assert_eq!(
+            value("isprime(2^61 - 1) + factor(91) + sqrt(144)"),
+            20.into()
+        );
+        // Leaving the integers gives floats
+        assert_eq!(value("7 / 2"), 3.5.into());
+        assert_eq!(value("2 ^ -2"), 0.25.into());
+        assert_eq!(value("2 * 1.5"), 3.0.into());
+        assert_eq!(value("sqrt(2) * 0"), 0.0.into());
+        assert!(matches!(
+            eval_with(&parse("2 ^ 10000000").unwrap(), &env),
+            Err(CalcError::Overflow { .. })
+        ));
+        assert!(matches!(
+            eval_with(&parse("100000!").unwrap(), &env),
+            Err(CalcError::Overflow { .. })
+        ));
+        assert!(matches!(
+            eval_with(&parse("5 mod (3 - 3)").unwrap(), &env),
+            Err(CalcError::DivisionByZero { .. })
+        ));
+        // Too large to test or factor exactly, and f64 would round them
+        assert!(matches!(
+            eval_with(&parse("isprime(3^5000)").unwrap(), &env),
+            Err(CalcError::Domain { .. })
+        ));
+        assert!(matches!(
+            eval_with(&parse("factor(2^300 + 1)").unwrap(), &env),
+            Err(CalcError::Domain { .. })
+        ));
+    }
+
+    #[test]
+    fn test_eval_int_mode() {
+        let mut env = Environment::new();
+        env.set_mode(Mode::Integer);
+        let value = |input: &str, env: &Environment| eval_with(&parse(input).unwrap(), env);
+        assert_eq!(value("10 / 5 + 2e3", &env).unwrap(), 2002.into());
+        assert_eq!(value("(-1) ^ -3 + sqrt(16.0)", &env).unwrap(), 3.into());
+        let error = value("7 / 2", &env).unwrap_err();
+        assert_eq!(error.kind(), "inexact_division");
+        assert_eq!(error.span(), Span::new(0, 5));
+        assert!(matches!(
+            value("2 ^ -1", &env),
+            Err(CalcError::InexactDivision { .. })
+        ));
+        // Values that are not whole stay floats
+        assert!(matches!(value("sqrt(2)", &env), Ok(Number::Float(_))));
+        assert_eq!(value("1.5 * 2", &env).unwrap(), 3.into());
+        assert_eq!(value("pi // 1", &env).unwrap(), 3.into());
+
+        // Other modes read integers exactly, and compute factorials exactly
+        env.set_mode(Mode::Rational);
+        assert_eq!(value("5! / 7", &env).unwrap().to_string(), "120/7");
+        env.set_mode(Mode::Decimal);
+        assert_eq!(
+            value("20!", &env).unwrap().to_string(),
+            "2432902008176640000"
+        );
+    }
+
+    #[test]
+    fn test_eval_complex() {
+        let mut env = Environment::new();
+        let value = |input: &str, env: &Environment| eval_with(&parse(input).unwrap(), env);
+        let show = |input: &str, env: &Environment| value(input, env).unwrap().to_string();
+        // Complex operands are complex in every mode
+        assert_eq!(show("(3+4i) * (1-2i)", &env), "11-2i");
+        assert_eq!(show("1 / (1+i)", &env), "0.5-0.5i");
+        assert_eq!(show("-i ^ 2 + conj(2i)", &env), "1-2i");
+        assert_eq!(value("(1+2i) * (1-2i)", &env).unwrap(), 5.0.into());
+        assert_eq!(
+            value("abs(3-4i) + re(2+i) + im(2+i)", &env).unwrap(),
+            8.0.into()
+        );
+        assert_eq!(show("sqrt(2i)", &env), "1+i");
+        assert!(matches!(
+            value("(2+i) % 2", &env),
+            Err(CalcError::Domain { .. })
+        ));
+        assert!(matches!(
+            value("max(i, 1)", &env),
+            Err(CalcError::Domain { .. })
+        ));
+        assert!(matches!(
+            value("i / (i - i)", &env),
+            Err(CalcError::DivisionByZero { .. })
+        ));
+        assert!(matches!(value("i!", &env), Err(CalcError::Domain { .. })));
+        // Real arguments keep their domain errors outside complex mode
+        assert!(matches!(
+            value("sqrt(-1)", &env),
+            Err(CalcError::Domain { .. })
+        ));
+        assert!(matches!(
+            value("(-8)^(1/3)", &env),
+            Err(CalcError::Domain { .. })
+        ));
+
+        env.set_mode(Mode::Complex);
+        assert_eq!(show("sqrt(-1)", &env), "i");
+        assert_eq!(show("sqrt(-4) + asin(0)", &env), "2i");
+        assert_eq!(show("(-8)^(1/3)", &env), "1+1.732050807568877i");
+        assert_eq!(show("ln(-1)", &env), "3.141592653589793i");
+        assert_eq!(value("2^10 + sqrt(16)", &env).unwrap(), 1028.into());
+        assert!(matches!(
+            value("ln(0)", &env),
+            Err(CalcError::Domain { .. })
+        ));
+        assert!(matches!(
+            value("tan(pi/2)", &env),
+            Err(CalcError::Domain { .. })
+        ));
+
+        // A variable called `i` hides the imaginary unit
+        env.set_variable("i", 3);
+        assert_eq!(value("i + 1", &env).unwrap(), 4.into());
+        assert_eq!(show("1i + 1", &env), "1+i");
+    }
+
+    #[test]
+    fn test_eval_units() {
+        let mut env = Environment::new();
+        let value = |input: &str, env: &Environment| eval_with(&parse(input).unwrap(), env);
+        let show = |input: &str, env: &Environment| value(input, env).unwrap().to_string();
+        assert_eq!(show("5 km + 300 m", &env), "5.3 km");
+        assert_eq!(show("60 mph to m/s", &env), "26.8224 m/s");
+        assert_eq!(show("3 kW * 2 h to kWh", &env), "6 kWh");
+        assert_eq!(show("-(2 m)^2 * 3 m", &env), "-12 m^3");
+        assert_eq!(show("sqrt(16 m^2) / 2 s", &env), "2 m/s");
+        assert_eq!(show("max(1 ft, 1 inch) in cm", &env), "30.48 cm");
+        assert_eq!(show("pi * 1 rad to deg", &env), "180 deg");
+        assert_eq!(value("2 km / 500 m", &env).unwrap(), 4.0.into());
+        assert_eq!(value("2 Hz * 3 s", &env).unwrap(), 6.0.into());
+        assert_eq!(value("1 W * 1 s / 1 J", &env).unwrap(), 1.0.into());
+        assert_eq!(value("5 km / 1 m", &env).unwrap(), 5000.0.into());
+        assert_eq!(value("1 h // 25 min", &env).unwrap(), 2.0.into());
+        assert_eq!(value("sin(90 deg)", &env).unwrap(), 1.0.into());
+        assert!(matches!(
+            value("5 m + 3 s", &env),
+            Err(CalcError::IncompatibleUnits { .. })
+        ));
+        assert!(matches!(
+            value("5 m + 3", &env),
+            Err(CalcError::IncompatibleUnits { .. })
+        ));
+        assert!(matches!(
+            value("60 mph to kg", &env),
+            Err(CalcError::IncompatibleUnits { .. })
+        ));
+        assert!(matches!(
+            value("sqrt(2 m)", &env),
+            Err(CalcError::Domain { .. })
+        ));
+        assert!(matches!(
+            value("ln(2 m)", &env),
+            Err(CalcError::Domain { .. })
+        ));
+        assert!(matches!(
+            value("2 ^ (1 s)", &env),
+            Err(CalcError::Domain { .. })
+        ));
+        assert!(matches!(
+            value("(1 + i) * 1 m", &env),
+            Err(CalcError::Domain { .. })
+        ));
+        assert!(matches!(
+            value("5 m / (0 s)", &env),
+            Err(CalcError::DivisionByZero { .. })
+        ));
+        // Named and base units go up to the 127th power
+        assert!(matches!(
+            parse("1 m^100 * 1 N^100 + 1"),
+            Err(CalcError::Overflow { .. })
+        ));
+        assert_eq!(
+            value("1 m^100 * 1 cm^100 + 1", &env),
+            Err(CalcError::Overflow {
+                span: Span::new(0, 18)
+            })
+        );
+        assert_eq!(
+            value("(1 m)^256 + 5", &env),
+            Err(CalcError::Overflow {
+                span: Span::new(0, 9)
+            })
+        );
+
+        // Names are only units after a number, so variables can share them
+        env.set_variable("m", 5);
+        env.set_variable("t", 2.5 * 60.0);
+        assert_eq!(value("m * t", &env).unwrap(), 750.0.into());
+        assert_eq!(show("2 m * m", &env), "10 m");
+        assert_eq!(show("t * 1 m to km", &env), "0.15 km");
+        assert_eq!(show("2 t to kg", &env), "2000 kg");
+        assert!(matches!(
+            parse("t m"),
+            Err(CalcError::UnexpectedToken { .. })
+        ));
+
+        // Quantities are floats in every mode, like the numbers that units cancel out of
+        env.set_mode(Mode::Rational);
+        assert_eq!(show("1/3 * 1 m + 1 cm", &env), "0.3433333333333333 m");
+        assert_eq!(show("6 m / 4 m", &env), "1.5");
+    }
+
+    #[test]
+    fn test_eval_converts_between_modes() {
+        let mut env = Environment::new();
+        env.set_mode(Mode::Decimal);
+        exec_with(&crate::parse_statement("price = 0.1").unwrap(), &mut env).unwrap();
+        assert!(matches!(env.variable("price"), Some(Number::Decimal(_))));
+        env.set_mode(Mode::Float);
+        let value = eval_with(&parse("price * 3").unwrap(), &env).unwrap();
+        assert_eq!(value, Number::Float(0.1 * 3.0));
+
+        env.set_mode(Mode::Rational);
+        exec_with(&crate::parse_statement("third = 1/3").unwrap(), &mut env).unwrap();
+        let value = eval_with(&parse("price + third").unwrap(), &env).unwrap();
+        assert_eq!(value.to_string(), "13/30");
+        env.set_mode(Mode::Decimal);
+        let value = eval_with(&parse("third").unwrap(), &env).unwrap();
+        assert_eq!(value.to_string(), "0.3333333333333333333333333333");
+    }
+
+    fn run_all(env: &mut Environment, inputs: &[&str]) -> Result<Option<Number>, CalcError> {
+        let mut result = Ok(None);
+        for input in inputs {
+            result = exec_with(&crate::parse_statement(input).unwrap(), env);
+        }
+        result
+    }
+
+    #[test]
+    fn test_user_functions() {
+        let mut env = Environment::new();
+        assert_eq!(run_all(&mut env, &["f(x, y) = x^2 + y"]).unwrap(), None);
+        assert_eq!(run_all(&mut env, &["f(3, 4)"]).unwrap(), Some(13.into()));
+        assert_eq!(
+            run_all(&mut env, &["f(f(1, 1), 0) * 2"]).unwrap(),
+            Some(8.into())
+        );
+        assert_eq!(
+            env.function("f").unwrap().to_string(),
+            "f(x, y) = x ^ 2 + y"
+        );
+
+        // Redefining replaces the old definition
+        assert_eq!(
+            run_all(&mut env, &["f(x, y) = x - y", "f(3, 4)"]).unwrap(),
+            Some((-1).into())
+        );
+    }
+
+    #[test]
+    fn test_user_functions_scoping() {
+        let mut env = Environment::new();
+        // Parameters shadow variables, and bodies see variables at call time
+        let inputs = ["x = 100", "rate = 2", "scale(x) = x * rate", "scale(3)"];
+        assert_eq!(run_all(&mut env, &inputs).unwrap(), Some(6.into()));
+        assert_eq!(
+            run_all(&mut env, &["rate = 10", "scale(3) + x"]).unwrap(),
+            Some(130.into())
+        );
+
+        // A function cannot see the parameters of its caller
+        let inputs = ["inner() = y", "outer(y) = inner()", "outer(1)"];
+        let error = run_all(&mut env, &inputs).unwrap_err();
+        let CalcError::InFunction { name, error, .. } = error else {
+            panic!("expected an error inside a function");
+        };
+        assert_eq!(name, "outer");
+        assert!(matches!(*error, CalcError::UnknownVariable { .. }));
+    }
+
+    #[test]
+    fn test_user_functions_recursion() {
+        let mut env = Environment::new();
+        let inputs = ["fact(n) = if(n, n * fact(n - 1), 1)", "fact(10)"];
+        assert_eq!(run_all(&mut env, &inputs).unwrap(), Some(3_628_800.into()));
+
+        let error = run_all(&mut env, &["loop(n) = loop(n + 1)", "loop(0)"]).unwrap_err();
+        let CalcError::InFunction { error, .. } = &error else {
+            panic!("expected an error inside a function");
+        };
+        assert!(matches!(
+            **error,
+            CalcError::RecursionLimit {
+                limit: MAX_CALL_DEPTH,
+                ..
+            }
+        ));
+        assert_eq!(error.code(), "E0019");
+    }
+
+    #[test]
+    fn test_user_function_errors() {
+        let mut env = Environment::new();
+        run_all(&mut env, &["inv(x) = 1 / x"]).unwrap();
+        let error = run_all(&mut env, &["2 + inv(0)"]).unwrap_err();
+        assert_eq!(error.span(), crate::Span::new(4, 10));
+        assert_eq!(error.code(), "E0003");
+        assert_eq!(error.to_string(), "In 'inv': Cannot divide by zero!");
+
+        assert!(matches!(
+            run_all(&mut env, &["inv(1, 2)"]),
+            Err(CalcError::WrongArity { found: 2, .. })
+        ));
+        assert!(matches!(
+            run_all(&mut env, &["sqrt(x) = x"]),
+            Err(CalcError::RedefineBuiltin { .. })
+        ));
+        assert!(matches!(
+            run_all(&mut env, &["g(x, x) = x"]),
+            Err(CalcError::DuplicateParameter { .. })
+        ));
+        let error = run_all(&mut env, &["ivn(2)"]).unwrap_err();
+        assert_eq!(error.help().as_deref(), Some("did you mean `inv`?"));
+    }
+
+    #[test]
+    fn test_if() {
+        let value = |input: &str| eval(&parse(input).unwrap());
+        assert_eq!(value("if(1, 2, 3)").unwrap(), 2.0);
+        assert_eq!(value("if(0, 2, 3)").unwrap(), 3.0);
+        // Only the chosen branch is evaluated
+        assert_eq!(value("if(1, 5, 1 / 0)").unwrap(), 5.0);
+        assert!(matches!(
+            value("if(1, 2)"),
+            Err(CalcError::WrongArity { .. })
+        ));
+    }
+
+    #[test]
+    fn test_eval_functions() {
+        let value = |input: &str| eval(&parse(input).unwrap()).unwrap();
+        assert_eq!(value("sqrt(16) + abs(-2)"), 6.0);
+        assert_eq!(value("log(2, 1024)"), 10.0);
+        assert_eq!(value("log10(1000) * log2(8)"), 9.0);
+        assert_eq!(value("max(1, 5, 3) - min(4, 2)"), 3.0);
+        assert_eq!(
+            value("floor(2.7) + ceil(2.1) + round(2.5) + trunc(-2.5)"),
+            6.0
+        );
+        assert_eq!(value("hypot(3, 4)"), 5.0);
+        assert_eq!(value("sin(0) + cos(0) + tanh(0)"), 1.0);
+        assert!((value("atan2(1, 1) * 4") - std::f64::consts::PI).abs() < 1e-12);
+        assert!((value("exp(ln(5))") - 5.0).abs() < 1e-12);
+        assert!((value("asinh(sinh(1.5))") - 1.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_eval_wrong_arity() {
+        let error = eval(&parse("sqrt(1, 2)").unwrap()).unwrap_err();
+        assert!(matches!(error, CalcError::WrongArity { found: 2, .. }));
+        assert_eq!(error.to_string(), "sqrt expects 1 argument but got 2");
+        assert!(matches!(
+            eval(&parse("max()").unwrap()),
+            Err(CalcError::WrongArity { found: 0, .. })
+        ));
+        assert!(matches!(
+            eval(&parse("log(8)").unwrap()),
+            Err(CalcError::WrongArity { .. })
+        ));
+    }
+
+    #[test]
+    fn test_eval_domain_errors() {
+        for input in ["sqrt(-1)", "ln(0)", "log(-2, 8)", "acos(2)", "(-8) ^ (1/3)"] {
+            assert!(
+                matches!(eval(&parse(input).unwrap()), Err(CalcError::Domain { .. })),
+                "{} should be a domain error",
+                input
+            );
+        }
+        let error = eval(&parse("1 + sqrt(-4)").unwrap()).unwrap_err();
+        assert_eq!(error.span(), crate::Span::new(4, 12));
+    }
+}
@@ -0,0 +1,320 @@
+use std::f64::consts::PI;
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_traits::Zero;
+
+use crate::integer;
+
+/// A built-in function callable as `name(arg, ...)`.
+#[derive(Debug)]
+pub struct Function {
+    pub name: &'static str,
+    pub arity: Arity,
+    /// One-line summary shown by `:help`.
+    pub description: &'static str,
+    /// Computes the result, or explains why the arguments are out of the
+    /// function's domain. The argument count has already been checked.
+    apply: fn(&[f64]) -> Result<f64, String>,
+}
+
+/// How many arguments a function accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exact(usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(self, count: usize) -> bool {
+        match self {
+            Arity::Exact(n) => count == n,
+            Arity::AtLeast(n) => count >= n,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (prefix, n) = match self {
+            Arity::Exact(n) => ("", *n),
+            Arity::AtLeast(n) => ("at least ", *n),
+        };
+        let plural = if n == 1 { "" } else { "s" };
+        write!(f, "{}{} argument{}", prefix, n, plural)
+    }
+}
+
+impl Function {
+    pub fn call(&self, args: &[f64]) -> Result<f64, String> {
+        (self.apply)(args)
+    }
+}
+
+/// Finds the built-in function called `name`.
+pub fn lookup(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|function| function.name == name)
+}
+
+macro_rules! unary {
+    ($name:expr, $description:expr, $apply:expr) => {
+        Function {
+            name: $name,
+            arity: Arity::Exact(1),
+            description: $description,
+            apply: |args| $apply(args[0]),
+        }
+    };
+}
+
+macro_rules! binary {
+    ($name:expr, $description:expr, $apply:expr) => {
+        Function {
+            name: $name,
+            arity: Arity::Exact(2),
+            description: $description,
+            apply: |args| $apply(args[0], args[1]),
+        }
+    };
+}
+
+/// Wraps an infallible function so it fits the `apply` signature.
+fn total(f: fn(f64) -> f64) -> impl Fn(f64) -> Result<f64, String> {
+    move |x| Ok(f(x))
+}
+
+/// Every built-in function, in the order `:help` lists them.
+pub const FUNCTIONS: &[Function] = &[
+    unary!("sqrt", "square root", |x: f64| {
+        require(x >= 0.0, "sqrt is only defined for x >= 0")?;
+        Ok(x.sqrt())
+    }),
+    unary!("cbrt", "cube root", total(f64::cbrt)),
+    unary!("abs", "absolute value", total(f64::abs)),
+    unary!("exp", "e raised to the power x", total(f64::exp)),
+    unary!("ln", "natural logarithm", |x: f64| {
+        require(x > 0.0, "ln is only defined for x > 0")?;
+        Ok(x.ln())
+    }),
+    unary!("log10", "base-10 logarithm", |x: f64| {
+        require(x > 0.0, "log10 is only defined for x > 0")?;
+        Ok(x.log10())
+    }),
+    unary!("log2", "base-2 logarithm", |x: f64| {
+        require(x > 0.0, "log2 is only defined for x > 0")?;
+        Ok(x.log2())
+    }),
+    binary!(
+        "log",
+        "logarithm of x in the given base: log(base, x)",
+        |base: f64, x: f64| {
+            require(
+                base > 0.0 && base != 1.0,
+                "log needs a base > 0 other than 1",
+            )?;
+            require(x > 0.0, "log is only defined for x > 0")?;
+            Ok(x.log(base))
+        }
+    ),
+    unary!("sin", "sine (radians)", total(f64::sin)),
+    unary!("cos", "cosine (radians)", total(f64::cos)),
+    unary!("tan", "tangent (radians)", |x: f64| {
+        // Odd multiples of pi/2 only come out as huge finite values in f64,
+        // so check the distance to the nearest pole instead
+        let to_pole = (x / PI - 0.5).round() * PI + PI / 2.0 - x;
+        require(
+            to_pole.abs() > 1e-12,
+            "tan is undefined at odd multiples of pi/2",
+        )?;
+        Ok(x.tan())
+    }),
+    unary!("asin", "inverse sine, in radians", |x: f64| {
+        require(
+            (-1.0..=1.0).contains(&x),
+            "asin is only defined for -1 <= x <= 1",
+        )?;
+        Ok(x.asin())
+    }),
+    unary!("acos", "inverse cosine, in radians", |x: f64| {
+        require(
+            (-1.0..=1.0).contains(&x),
+            "acos is only defined for -1 <= x <= 1",
+        )?;
+        Ok(x.acos())
+    }),
+    unary!("atan", "inverse tangent, in radians", total(f64::atan)),
+    unary!("sinh", "hyperbolic sine", total(f64::sinh)),
+    unary!("cosh", "hyperbolic cosine", total(f64::cosh)),
+    unary!("tanh", "hyperbolic tangent", total(f64::tanh)),
+    unary!("asinh", "inverse hyperbolic sine", total(f64::asinh)),
+    unary!("acosh", "inverse hyperbolic cosine", |x: f64| {
+        require(x >= 1.0, "acosh is only defined for x >= 1")?;
+        Ok(x.acosh())
+    }),
+    unary!("atanh", "inverse hyperbolic tangent", |x: f64| {
+        require(x > -1.0 && x < 1.0, "atanh is only defined for -1 < x < 1")?;
+        Ok(x.atanh())
+    }),
+    unary!("floor", "round down to an integer", total(f64::floor)),
+    unary!("ceil", "round up to an integer", total(f64::ceil)),
+    unary!(
+        "round",
+        "round to the nearest integer, halves away from zero",
+        total(f64::round)
+    ),
+    unary!("trunc", "round towards zero", total(f64::trunc)),
+    Function {
+        name: "min",
+        arity: Arity::AtLeast(1),
+        description: "smallest of the arguments",
+        apply: |args| Ok(args.iter().copied().fold(f64::INFINITY, f64::min)),
+    },
+    Function {
+        name: "max",
+        arity: Arity::AtLeast(1),
+        description: "largest of the arguments",
+        apply: |args| Ok(args.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+    },
+    binary!(
+        "hypot",
+        "length of the hypotenuse: sqrt(x^2 + y^2)",
+        |x: f64, y: f64| Ok(x.hypot(y))
+    ),
+    unary!("re", "real part of a complex number", total(|x| x)),
+    unary!("im", "imaginary part of a complex number", total(|_| 0.0)),
+    unary!(
+        "arg",
+        "angle of a complex number, in radians",
+        total(|x| 0.0f64.atan2(x))
+    ),
+    unary!("conj", "complex conjugate", total(|x| x)),
+    binary!(
+        "atan2",
+        "angle of the point (x, y): atan2(y, x)",
+        |y: f64, x: f64| Ok(y.atan2(x))
+    ),
+    Function {
+        name: "gcd",
+        arity: Arity::AtLeast(2),
+        description: "greatest common divisor of integers",
+        apply: |args| Ok(integer::to_f64(&integer::gcd(&integers("gcd", args)?))),
+    },
+    Function {
+        name: "lcm",
+        arity: Arity::AtLeast(2),
+        description: "least common multiple of integers",
+        apply: |args| Ok(integer::to_f64(&integer::lcm(&integers("lcm", args)?))),
+    },
+    Function {
+        name: "powmod",
+        arity: Arity::Exact(3),
+        description: "x^n modulo m, for integers: powmod(x, n, m)",
+        apply: |args| {
+            let args = integers("powmod", args)?;
+            let result = integer::powmod(&args[0], &args[1], &args[2]).ok_or_else(|| {
+                if args[2].is_zero() {
+                    "powmod needs a modulus other than 0".to_string()
+                } else {
+                    "powmod with a negative power needs x and m without common factors".to_string()
+                }
+            })?;
+            Ok(integer::to_f64(&result))
+        },
+    },
+    unary!("isprime", "1 if the integer x is prime, otherwise 0", |x| {
+        let x = integers("isprime", &[x])?;
+        Ok(if integer::is_prime(&x[0])? { 1.0 } else { 0.0 })
+    }),
+    unary!(
+        "factor",
+        "smallest prime factor of an integer; :factor lists them all",
+        |x| {
+            let x = integers("factor", &[x])?;
+            let factors = integer::factor(&x[0])?;
+            Ok(integer::to_f64(&factors[0].0))
+        }
+    ),
+];
+
+/// The arguments of an integer function as integers, or an error naming
+/// the function if any of them is not whole.
+fn integers(name: &str, args: &[f64]) -> Result<Vec<BigInt>, String> {
+    args.iter()
+        .map(|&arg| integer::from_f64(arg))
+        .collect::<Option<_>>()
+        .ok_or_else(|| format!("{} is only defined for integers", name))
+}
+
+fn require(condition: bool, message: &str) -> Result<(), String> {
+    if condition {
+        Ok(())
+    } else {
+        Err(message.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: &[f64]) -> Result<f64, String> {
+        lookup(name).unwrap().call(args)
+    }
+
+    #[test]
+    fn test_names_are_unique() {
+        for (i, function) in FUNCTIONS.iter().enumerate() {
+            assert!(
+                FUNCTIONS[i + 1..]
+                    .iter()
+                    .all(|other| other.name != function.name),
+                "{} is defined twice",
+                function.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_arity_display() {
+        assert_eq!(Arity::Exact(1).to_string(), "1 argument");
+        assert_eq!(Arity::Exact(2).to_string(), "2 arguments");
+        assert_eq!(Arity::AtLeast(1).to_string(), "at least 1 argument");
+    }
+
+    #[test]
+    fn test_domains() {
+        assert!(call("sqrt", &[-1.0]).is_err());
+        assert!(call("ln", &[0.0]).is_err());
+        assert!(call("log", &[1.0, 5.0]).is_err());
+        assert!(call("asin", &[1.5]).is_err());
+        assert!(call("acosh", &[0.5]).is_err());
+        assert!(call("atanh", &[1.0]).is_err());
+        assert!(call("tan", &[PI / 2.0]).is_err());
+        assert!(call("tan", &[-3.0 * PI / 2.0]).is_err());
+        assert!(call("gcd", &[1.5, 3.0]).is_err());
+        assert!(call("powmod", &[2.0, 3.0, 0.0]).is_err());
+        assert!(call("powmod", &[2.0, -1.0, 4.0]).is_err());
+        assert!(call("factor", &[1.0]).is_err());
+        assert!(call("isprime", &[f64::NAN]).is_err());
+    }
+
+    #[test]
+    fn test_values() {
+        assert_eq!(call("sqrt", &[16.0]), Ok(4.0));
+        assert_eq!(call("cbrt", &[-27.0]), Ok(-3.0));
+        assert_eq!(call("log", &[2.0, 8.0]), Ok(3.0));
+        assert_eq!(call("min", &[3.0, -1.0, 2.0]), Ok(-1.0));
+        assert_eq!(call("max", &[3.0]), Ok(3.0));
+        assert_eq!(call("hypot", &[3.0, 4.0]), Ok(5.0));
+        assert_eq!(call("round", &[-2.5]), Ok(-3.0));
+        assert_eq!(call("trunc", &[-2.7]), Ok(-2.0));
+        assert_eq!(call("gcd", &[12.0, 18.0]), Ok(6.0));
+        assert_eq!(call("lcm", &[4.0, 6.0, 10.0]), Ok(60.0));
+        assert_eq!(call("powmod", &[4.0, 13.0, 497.0]), Ok(445.0));
+        assert_eq!(call("isprime", &[97.0]), Ok(1.0));
+        assert_eq!(call("factor", &[91.0]), Ok(7.0));
+        assert_eq!(call("factor", &[360.0]), Ok(2.0));
+        assert_eq!(call("im", &[3.0]), Ok(0.0));
+        assert_eq!(call("arg", &[-2.0]), Ok(PI));
+    }
+}
@@ -0,0 +1,371 @@
+//! Whole numbers of any size.
+//!
+//! Float mode calculates with them as long as every operand is whole, so
+//! `2^200` and `30!` come out exact; int mode also refuses divisions that
+//! leave a remainder. Arithmetic, powers with a non-negative exponent and
+//! the functions in [`call`] are exact. Anything else is computed as `f64`.
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{FromPrimitive, One, Signed, ToPrimitive, Zero};
+
+/// Results that would need more bits than this go through `f64`, which
+/// reports them as an overflow instead of building a number with hundreds
+/// of thousands of digits.
+const MAX_EXACT_BITS: u64 = 1 << 20;
+
+/// The largest `n` whose `n!` is computed; `50000!` has 213,237 digits.
+pub const MAX_FACTORIAL: u64 = 50_000;
+
+/// The most bits `isprime` tests, about 617 digits; a prime that size
+/// takes a fraction of a second.
+pub const MAX_PRIME_BITS: u64 = 2048;
+
+/// The most bits `factor` and `:factor` take, about 77 digits. Pollard's
+/// rho gets slower with the size of the number, so anything larger could
+/// take minutes to give up on.
+pub const MAX_FACTOR_BITS: u64 = 256;
+
+/// Trial division finds factors up to this before Pollard's rho takes over.
+const TRIAL_DIVISION_LIMIT: u32 = 10_000;
+
+/// Steps Pollard's rho may take per attempt before factoring gives up,
+/// which is enough to find factors up to about 10^9.
+const MAX_RHO_STEPS: u64 = 1 << 16;
+
+/// Reads a literal written with digits only, such as `1000`. Literals with
+/// a point or an exponent are not integers, even when their value is.
+pub fn parse(text: &str) -> Option<BigInt> {
+    if !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit()) {
+        text.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// The integer equal to `value`, if it is finite and whole.
+pub fn from_f64(value: f64) -> Option<BigInt> {
+    if value.fract() == 0.0 {
+        BigInt::from_f64(value)
+    } else {
+        None
+    }
+}
+
+/// The nearest `f64` to `value`, which is infinite if it is too large.
+pub fn to_f64(value: &BigInt) -> f64 {
+    value.to_f64().unwrap_or(f64::NAN)
+}
+
+/// `base ^ exponent` for non-negative exponents with a result of sensible
+/// size, or `None` when the power has to be computed as `f64`.
+pub fn pow(base: &BigInt, exponent: &BigInt) -> Option<BigInt> {
+    let exponent = exponent.to_u32()?;
+    // Powers of 0, 1 and -1 stay small however large the exponent
+    let bits = base.bits().saturating_mul(u64::from(exponent));
+    if base.bits() > 1 && bits > MAX_EXACT_BITS {
+        return None;
+    }
+    Some(base.pow(exponent))
+}
+
+/// `n!`. `n` must be at most [`MAX_FACTORIAL`].
+pub fn factorial(n: u64) -> BigInt {
+    product(1, n)
+}
+
+/// The product of `low..=high`, split in halves so that the numbers
+/// multiplied together stay about the same size.
+fn product(low: u64, high: u64) -> BigInt {
+    if low > high {
+        return BigInt::one();
+    }
+    if high - low < 16 {
+        return (low..=high).map(BigInt::from).product();
+    }
+    let middle = low + (high - low) / 2;
+    product(low, middle) * product(middle + 1, high)
+}
+
+/// The greatest common divisor of `values`, never negative.
+pub fn gcd(values: &[BigInt]) -> BigInt {
+    values
+        .iter()
+        .fold(BigInt::zero(), |result, value| result.gcd(value))
+}
+
+/// The least common multiple of `values`, never negative. It is zero if
+/// any value is.
+pub fn lcm(values: &[BigInt]) -> BigInt {
+    values
+        .iter()
+        .fold(BigInt::one(), |result, value| result.lcm(value))
+}
+
+/// `base ^ exponent mod modulus`, in `0..|modulus|`. A negative exponent
+/// uses the inverse of `base`, so there is no result if `base` has none or
+/// `modulus` is zero.
+pub fn powmod(base: &BigInt, exponent: &BigInt, modulus: &BigInt) -> Option<BigInt> {
+    if modulus.is_zero() {
+        return None;
+    }
+    let modulus = modulus.abs();
+    if exponent.is_negative() {
+        let inverse = base.modinv(&modulus)?;
+        Some(inverse.modpow(&-exponent, &modulus))
+    } else {
+        Some(base.modpow(exponent, &modulus))
+    }
+}
+
+/// Whether `n` is prime. Exact for `n` below 3.3 * 10^24; beyond that a
+/// composite is mistaken for a prime with negligible probability. Fails if
+/// `n` has more than [`MAX_PRIME_BITS`] bits.
+pub fn is_prime(n: &BigInt) -> Result<bool, String> {
+    if n.bits() > MAX_PRIME_BITS {
+        return Err(format!(
+            "isprime only tests integers below 2^{}",
+            MAX_PRIME_BITS
+        ));
+    }
+    const BASES: [u32; 20] = [
+        2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71,
+    ];
+    let one = BigInt::one();
+    if *n <= one {
+        return Ok(false);
+    }
+    for base in BASES {
+        let base = BigInt::from(base);
+        if *n == base {
+            return Ok(true);
+        }
+        if (n % &base).is_zero() {
+            return Ok(false);
+        }
+    }
+    // Miller-Rabin: write n - 1 as d * 2^s with d odd
+    let n_minus_one = n - &one;
+    let s = n_minus_one.trailing_zeros().unwrap_or(0);
+    let d = &n_minus_one >> s;
+    'witness: for base in BASES {
+        let mut x = BigInt::from(base).modpow(&d, n);
+        if x == one || x == n_minus_one {
+            continue;
+        }
+        for _ in 1..s {
+            x = &x * &x % n;
+            if x == n_minus_one {
+                continue 'witness;
+            }
+        }
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// The prime factors of `|n|` with their multiplicities, smallest first.
+/// Fails if `|n|` is below 2, has more than [`MAX_FACTOR_BITS`] bits or has
+/// factors too large to find.
+pub fn factor(n: &BigInt) -> Result<Vec<(BigInt, u32)>, String> {
+    let mut n = n.abs();
+    if n < BigInt::from(2) {
+        return Err("only integers other than 0, 1 and -1 have prime factors".to_string());
+    }
+    if n.bits() > MAX_FACTOR_BITS {
+        return Err(format!(
+            "only integers below 2^{} can be factored",
+            MAX_FACTOR_BITS
+        ));
+    }
+    let whole = n.clone();
+    let mut primes = Vec::new();
+    for p in 2..TRIAL_DIVISION_LIMIT {
+        let p = BigInt::from(p);
+        if &p * &p > n {
+            break;
+        }
+        while (&n % &p).is_zero() {
+            n /= &p;
+            primes.push(p.clone());
+        }
+    }
+    let mut rest = vec![n];
+    while let Some(n) = rest.pop() {
+        if n.is_one() {
+            continue;
+        }
+        if is_prime(&n)? {
+            primes.push(n);
+            continue;
+        }
+        let divisor = rho(&n)
+            .ok_or_else(|| format!("the prime factors of {} are too large to find", whole))?;
+        rest.push(&n / &divisor);
+        rest.push(divisor);
+    }
+    primes.sort();
+    let mut factors: Vec<(BigInt, u32)> = Vec::new();
+    for prime in primes {
+        match factors.last_mut() {
+            Some((last, count)) if *last == prime => *count += 1,
+            _ => factors.push((prime, 1)),
+        }
+    }
+    Ok(factors)
+}
+
+/// A divisor of the composite `n` other than 1 and `n`, found with
+/// Pollard's rho, or `None` if it takes too long.
+fn rho(n: &BigInt) -> Option<BigInt> {
+    for c in 1u32..=10 {
+        let c = BigInt::from(c);
+        let step = |x: &BigInt| (x * x + &c) % n;
+        let (mut x, mut y) = (BigInt::from(2), BigInt::from(2));
+        let mut steps = 0;
+        loop {
+            x = step(&x);
+            y = step(&step(&y));
+            let divisor = (&x - &y).abs().gcd(n);
+            if divisor == *n {
+                break;
+            }
+            if !divisor.is_one() {
+                return Some(divisor);
+            }
+            steps += 1;
+            if steps > MAX_RHO_STEPS {
+                return None;
+            }
+        }
+    }
+    None
+}
+
+/// The exact version of the built-in function `name`, or `None` if it has
+/// none or its result is not a whole number for these arguments, in which
+/// case the `f64` version is used. Arguments outside a function's domain
+/// return `None`, so the error comes from the `f64` version too, except for
+/// `isprime` and `factor`: `f64` would round an integer too large for
+/// them, so they fail here.
+pub fn call(name: &str, args: &[BigInt]) -> Option<Result<BigInt, String>> {
+    let result = match name {
+        "sqrt" if !args[0].is_negative() => root(&args[0], 2)?,
+        "cbrt" => root(&args[0], 3)?,
+        "abs" => args[0].abs(),
+        "floor" | "ceil" | "round" | "trunc" => args[0].clone(),
+        "min" => args.iter().min()?.clone(),
+        "max" => args.iter().max()?.clone(),
+        "hypot" => root(&(&args[0] * &args[0] + &args[1] * &args[1]), 2)?,
+        "gcd" => gcd(args),
+        "lcm" => lcm(args),
+        "powmod" => powmod(&args[0], &args[1], &args[2])?,
+        "isprime" => return Some(is_prime(&args[0]).map(|prime| BigInt::from(u8::from(prime)))),
+        "factor" => return Some(factor(&args[0]).map(|factors| factors[0].0.clone())),
+        _ => return None,
+    };
+    Some(Ok(result))
+}
+
+/// The `degree`th root of `value` if it is whole.
+fn root(value: &BigInt, degree: u32) -> Option<BigInt> {
+    let root = value.nth_root(degree);
+    (root.pow(degree) == *value).then_some(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(text: &str) -> BigInt {
+        text.parse().unwrap()
+    }
+
+    fn ns(values: &[i64]) -> Vec<BigInt> {
+        values.iter().map(|&value| BigInt::from(value)).collect()
+    }
+
+    #[test]
+    fn test_parse_and_convert() {
+        assert_eq!(parse("007"), Some(n("7")));
+        assert_eq!(parse("1e3"), None);
+        assert_eq!(parse("2.0"), None);
+        assert_eq!(from_f64(-3.0), Some(n("-3")));
+        assert_eq!(from_f64(0.5), None);
+        assert_eq!(from_f64(f64::INFINITY), None);
+        assert_eq!(to_f64(&n("1").pow(2000)), 1.0);
+        assert_eq!(to_f64(&BigInt::from(10).pow(400)), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_powers_and_factorials() {
+        assert_eq!(
+            pow(&n("2"), &n("100")),
+            Some(n("1267650600228229401496703205376"))
+        );
+        assert_eq!(pow(&n("-1"), &n("1000000001")), Some(n("-1")));
+        assert_eq!(pow(&n("2"), &n("-1")), None);
+        assert_eq!(pow(&n("10"), &n("1000000")), None);
+        assert_eq!(factorial(0), n("1"));
+        assert_eq!(factorial(30), n("265252859812191058636308480000000"));
+        assert_eq!(factorial(100).to_string().len(), 158);
+    }
+
+    #[test]
+    fn test_number_theory() {
+        assert_eq!(gcd(&ns(&[12, -18, 30])), n("6"));
+        assert_eq!(lcm(&ns(&[4, 6, -10])), n("60"));
+        assert_eq!(powmod(&n("4"), &n("13"), &n("497")), Some(n("445")));
+        assert_eq!(powmod(&n("-2"), &n("3"), &n("5")), Some(n("2")));
+        assert_eq!(powmod(&n("3"), &n("-1"), &n("7")), Some(n("5")));
+        assert_eq!(powmod(&n("2"), &n("-1"), &n("4")), None);
+        assert_eq!(powmod(&n("2"), &n("3"), &n("0")), None);
+    }
+
+    #[test]
+    fn test_primes() {
+        let primes: Vec<i64> = (0..40)
+            .filter(|&i| is_prime(&BigInt::from(i)).unwrap())
+            .collect();
+        assert_eq!(primes, vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37]);
+        // A Carmichael number and a large strong pseudoprime to base 2
+        assert_eq!(is_prime(&n("561")), Ok(false));
+        assert_eq!(is_prime(&n("3215031751")), Ok(false));
+        assert_eq!(
+            is_prime(&n("170141183460469231731687303715884105727")),
+            Ok(true)
+        );
+        assert!(is_prime(&(BigInt::one() << MAX_PRIME_BITS)).is_err());
+        assert_eq!(
+            factor(&n("-360")),
+            Ok(vec![(n("2"), 3), (n("3"), 2), (n("5"), 1)])
+        );
+        // Primes beyond trial division
+        assert_eq!(
+            factor(&(n("1000000007") * n("998244353") * n("998244353"))),
+            Ok(vec![(n("998244353"), 2), (n("1000000007"), 1)])
+        );
+        assert!(factor(&n("1")).is_err());
+        assert!(factor(&(BigInt::one() << MAX_FACTOR_BITS)).is_err());
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(call("sqrt", &ns(&[144])), Some(Ok(n("12"))));
+        assert_eq!(call("sqrt", &ns(&[2])), None);
+        assert_eq!(call("cbrt", &ns(&[-27])), Some(Ok(n("-3"))));
+        assert_eq!(call("hypot", &ns(&[5, 12])), Some(Ok(n("13"))));
+        assert_eq!(call("isprime", &ns(&[97])), Some(Ok(n("1"))));
+        assert_eq!(call("factor", &ns(&[91])), Some(Ok(n("7"))));
+        assert_eq!(call("factor", &ns(&[360])), Some(Ok(n("2"))));
+        assert_eq!(
+            call("factor", &[n("1000000007") * n("999999937")]),
+            Some(Ok(n("999999937")))
+        );
+        assert!(call("factor", &ns(&[0])).unwrap().is_err());
+        assert!(call("isprime", &[BigInt::from(3).pow(5000)])
+            .unwrap()
+            .is_err());
+        assert_eq!(call("sin", &ns(&[0])), None);
+    }
+}
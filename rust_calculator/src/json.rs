@@ -0,0 +1,237 @@
+use std::fmt;
+
+use crate::error::CalcError;
+use crate::number::Number;
+use crate::rational;
+
+/// The outcome of evaluating one line of input, written as a single line of
+/// JSON so that a stream of them forms JSON Lines:
+///
+/// ```text
+/// {"input":"10 / 4","value":2.5,"error":null}
+/// {"input":"1 / 0","value":null,"error":{"kind":"division_by_zero","code":"E0003","message":"Cannot divide by zero!","help":null,"span":{"start":4,"end":5}}}
+/// ```
+///
+/// Spans are byte offsets into `input`. Integers, decimals and whole
+/// numbers of rational mode are written with all their digits, which JSON allows even
+/// where a reader's doubles cannot hold them. Other fractions are written as
+/// the nearest double, with the exact value in a `"fraction"` field such as
+/// `"1/3"`. Infinity and NaN have no JSON number form, so they are written as the
+/// strings `"inf"`, `"-inf"` and `"NaN"`. Complex numbers are written as a
+/// string such as `"3+4i"`, with their parts in `"re"` and `"im"` fields.
+/// Quantities are written as the number in their unit, with the unit in a
+/// `"unit"` field such as `"km"`.
+/// Statements without a value, like function definitions, have neither a
+/// value nor an error.
+///
+/// ```
+/// use rust_calculator::{json::Evaluation, Calculator};
+///
+/// let mut calculator = Calculator::new();
+/// let outcome = calculator.evaluate("2 ^ 10");
+/// assert_eq!(
+///     Evaluation::new("2 ^ 10", &outcome).to_string(),
+///     r#"{"input":"2 ^ 10","value":1024,"error":null}"#
+/// );
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Evaluation<'a> {
+    input: &'a str,
+    line: Option<usize>,
+    outcome: &'a Result<Option<Number>, CalcError>,
+}
+
+impl<'a> Evaluation<'a> {
+    pub fn new(input: &'a str, outcome: &'a Result<Option<Number>, CalcError>) -> Evaluation<'a> {
+        Evaluation {
+            input,
+            line: None,
+            outcome,
+        }
+    }
+
+    /// Adds a `"line"` field, for input that came from a file.
+    pub fn with_line(mut self, line: usize) -> Evaluation<'a> {
+        self.line = Some(line);
+        self
+    }
+}
+
+impl fmt::Display for Evaluation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{\"input\":")?;
+        write_string(f, self.input)?;
+        if let Some(line) = self.line {
+            write!(f, ",\"line\":{}", line)?;
+        }
+        write!(f, ",\"value\":")?;
+        match self.outcome {
+            Ok(Some(value)) => write_number(f, value)?,
+            _ => write!(f, "null")?,
+        }
+        if let Ok(Some(Number::Rational(value))) = self.outcome {
+            if !value.is_integer() {
+                write!(f, ",\"fraction\":\"{}/{}\"", value.numer(), value.denom())?;
+            }
+        }
+        if let Ok(Some(Number::Complex(value))) = self.outcome {
+            write!(f, ",\"re\":")?;
+            write_number(f, &Number::Float(value.re))?;
+            write!(f, ",\"im\":")?;
+            write_number(f, &Number::Float(value.im))?;
+        }
+        if let Ok(Some(Number::Quantity(quantity))) = self.outcome {
+            write!(f, ",\"unit\":")?;
+            write_string(f, &quantity.unit.to_string())?;
+        }
+        write!(f, ",\"error\":")?;
+        match self.outcome {
+            Ok(_) => write!(f, "null")?,
+            Err(error) => write_error(f, error)?,
+        }
+        write!(f, "}}")
+    }
+}
+
+fn write_error(f: &mut fmt::Formatter, error: &CalcError) -> fmt::Result {
+    write!(
+        f,
+        "{{\"kind\":\"{}\",\"code\":\"{}\",\"message\":",
+        error.kind(),
+        error.code()
+    )?;
+    write_string(f, &error.to_string())?;
+    write!(f, ",\"help\":")?;
+    match error.help() {
+        Some(help) => write_string(f, &help)?,
+        None => write!(f, "null")?,
+    }
+    let span = error.span();
+    write!(
+        f,
+        ",\"span\":{{\"start\":{},\"end\":{}}}}}",
+        span.start, span.end
+    )
+}
+
+fn write_number(f: &mut fmt::Formatter, value: &Number) -> fmt::Result {
+    match value {
+        Number::Float(value) if !value.is_finite() => write!(f, "\"{}\"", value),
+        Number::Rational(value) if !value.is_integer() => {
+            write_number(f, &Number::Float(rational::to_f64(value)))
+        }
+        Number::Complex(_) => write_string(f, &value.to_string()),
+        Number::Quantity(quantity) => write_number(f, &Number::Float(quantity.value)),
+        value => write!(f, "{}", value),
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Calculator;
+
+    fn json(input: &str) -> String {
+        let outcome = Calculator::new().evaluate(input);
+        Evaluation::new(input, &outcome).to_string()
+    }
+
+    #[test]
+    fn test_values() {
+        assert_eq!(
+            json("0.1 + 0.2"),
+            r#"{"input":"0.1 + 0.2","value":0.30000000000000004,"error":null}"#
+        );
+        assert_eq!(
+            json("-inf"),
+            r#"{"input":"-inf","value":"-inf","error":null}"#
+        );
+        assert_eq!(
+            json("f(x) = x"),
+            r#"{"input":"f(x) = x","value":null,"error":null}"#
+        );
+    }
+
+    #[test]
+    fn test_decimal_values() {
+        let mut calculator = Calculator::new();
+        calculator.environment_mut().set_mode(crate::Mode::Decimal);
+        let outcome = calculator.evaluate("0.1 + 0.2");
+        assert_eq!(
+            Evaluation::new("0.1 + 0.2", &outcome).to_string(),
+            r#"{"input":"0.1 + 0.2","value":0.3,"error":null}"#
+        );
+        let outcome = calculator.evaluate("1 / 3");
+        assert!(Evaluation::new("1 / 3", &outcome)
+            .to_string()
+            .contains(r#""value":0.3333333333333333333333333333,"#));
+    }
+
+    #[test]
+    fn test_rational_values() {
+        let mut calculator = Calculator::new();
+        calculator.environment_mut().set_mode(crate::Mode::Rational);
+        let outcome = calculator.evaluate("1/4 + 1/4");
+        assert_eq!(
+            Evaluation::new("1/4 + 1/4", &outcome).to_string(),
+            r#"{"input":"1/4 + 1/4","value":0.5,"fraction":"1/2","error":null}"#
+        );
+        let outcome = calculator.evaluate("2^70");
+        assert_eq!(
+            Evaluation::new("2^70", &outcome).to_string(),
+            r#"{"input":"2^70","value":1180591620717411303424,"error":null}"#
+        );
+    }
+
+    #[test]
+    fn test_complex_values() {
+        assert_eq!(
+            json("(1+2i)^2"),
+            r#"{"input":"(1+2i)^2","value":"-3+4i","re":-3,"im":4,"error":null}"#
+        );
+    }
+
+    #[test]
+    fn test_quantities() {
+        assert_eq!(
+            json("5 km + 300 m"),
+            r#"{"input":"5 km + 300 m","value":5.3,"unit":"km","error":null}"#
+        );
+        assert!(json("5 m + 3 s").contains(r#""kind":"incompatible_units","code":"E0021""#));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            json("1 / 0"),
+            r#"{"input":"1 / 0","value":null,"error":{"kind":"division_by_zero","code":"E0003","message":"Cannot divide by zero!","help":null,"span":{"start":4,"end":5}}}"#
+        );
+        assert!(json("5 x 3").contains(r#""kind":"unknown_operator","code":"E0002","message":"Unsupported operator: x","help":"did you mean `*`?""#));
+    }
+
+    #[test]
+    fn test_line_and_escaping() {
+        let outcome = Ok(Some(1.0.into()));
+        let evaluation = Evaluation::new("\"a\\b\"\t\u{1}", &outcome).with_line(7);
+        assert_eq!(
+            evaluation.to_string(),
+            r#"{"input":"\"a\\b\"\t\u0001","line":7,"value":1,"error":null}"#
+        );
+    }
+}
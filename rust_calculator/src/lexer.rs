@@ -0,0 +1,365 @@
+use crate::error::CalcError;
+use crate::units;
+
+/// A byte range `start..end` into the original input line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Number(f64),
+    /// A number followed by `i`, such as `4i` or `0.5i`.
+    Imaginary(f64),
+    Identifier(String),
+    Operator(&'static str),
+    LeftParen,
+    RightParen,
+    Comma,
+    Equals,
+    /// `$n`, a reference to the n-th result of the session.
+    ResultRef(usize),
+}
+
+/// Operator symbols, longest first so `**` is not read as two `*`.
+const OPERATORS: &[&str] = &["**", "//", "+", "-", "*", "/", "^", "%", "!"];
+
+/// Words that are operators rather than names.
+const WORD_OPERATORS: &[&str] = &["mod"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+/// Splits the input into tokens. Whitespace is only needed to separate two
+/// numbers or names, so `5+3`, `5 +3` and `5 + 3` all produce the same tokens.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, CalcError> {
+    let mut lexer = Lexer {
+        input,
+        chars: input.char_indices().peekable(),
+    };
+    let mut tokens = Vec::new();
+
+    while let Some(token) = lexer.next_token()? {
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Lexer<'a> {
+    input: &'a str,
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl<'a> Lexer<'a> {
+    fn next_token(&mut self) -> Result<Option<Token>, CalcError> {
+        // Skip whitespace between tokens
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+
+        let Some(&(start, c)) = self.chars.peek() else {
+            return Ok(None);
+        };
+
+        let kind = if c.is_ascii_digit() || c == '.' {
+            self.number(start)?
+        } else if c == '$' && self.input[start + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+            self.chars.next();
+            let end = self.eat_while(|c| c.is_ascii_digit());
+            let digits = &self.input[start + 1..end];
+            // Too many digits to fit in a usize can only refer to a missing result
+            TokenKind::ResultRef(digits.parse().unwrap_or(usize::MAX))
+        } else if c.is_alphabetic() || c == '_' {
+            let end = self.eat_while(|c| c.is_alphanumeric() || c == '_');
+            let word = &self.input[start..end];
+            match WORD_OPERATORS.iter().find(|operator| **operator == word) {
+                Some(operator) => TokenKind::Operator(operator),
+                None => TokenKind::Identifier(word.to_string()),
+            }
+        } else if let Some(operator) = OPERATORS
+            .iter()
+            .find(|operator| self.input[start..].starts_with(**operator))
+        {
+            // Operators are ASCII, so each byte is one character
+            for _ in 0..operator.len() {
+                self.chars.next();
+            }
+            TokenKind::Operator(operator)
+        } else {
+            self.chars.next();
+            match c {
+                '(' => TokenKind::LeftParen,
+                ')' => TokenKind::RightParen,
+                ',' => TokenKind::Comma,
+                '=' => TokenKind::Equals,
+                _ => {
+                    // Report the whole run of symbols, e.g. "&" or "=>"
+                    let end = self.eat_while(|c| {
+                        !c.is_whitespace() && !c.is_alphanumeric() && !"(),=".contains(c)
+                    });
+                    return Err(CalcError::UnknownOperator {
+                        operator: self.input[start..end].to_string(),
+                        span: Span::new(start, end),
+                    });
+                }
+            }
+        };
+
+        let end = self.offset();
+        Ok(Some(Token {
+            kind,
+            span: Span::new(start, end),
+        }))
+    }
+
+    /// Reads digits with an optional fraction and exponent, e.g. `1.5e-3`.
+    fn number(&mut self, start: usize) -> Result<TokenKind, CalcError> {
+        self.eat_while(|c| c.is_ascii_digit() || c == '.');
+
+        if let Some(&(_, 'e' | 'E')) = self.chars.peek() {
+            // Only treat the `e` as an exponent if digits follow it
+            let rest = &self.input[self.offset() + 1..];
+            let digits = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+            if digits.starts_with(|c: char| c.is_ascii_digit()) {
+                self.chars.next();
+                self.chars.next_if(|(_, c)| *c == '+' || *c == '-');
+                self.eat_while(|c| c.is_ascii_digit());
+            }
+        }
+
+        // A lone `i` makes the number imaginary
+        let digits_end = self.offset();
+        let rest = &self.input[digits_end..];
+        let imaginary = rest.starts_with('i')
+            && !rest[1..].starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '.');
+        if imaginary {
+            self.chars.next();
+        }
+
+        // A unit glued to a number, as in "5km", is a token of its own
+        let word = rest
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .next();
+        let unit = !imaginary && word.is_some_and(units::is_unit);
+
+        // Letters glued to a number (e.g. "3abc") make the whole word invalid
+        let end = if unit {
+            digits_end
+        } else {
+            self.eat_while(|c| c.is_alphanumeric() || c == '_' || c == '.')
+        };
+        let text = &self.input[start..if imaginary { digits_end } else { end }];
+        let invalid = || CalcError::ParseNumber {
+            text: self.input[start..end].to_string(),
+            span: Span::new(start, end),
+        };
+        let value: f64 = text.parse().map_err(|_| invalid())?;
+        Ok(if imaginary {
+            TokenKind::Imaginary(value)
+        } else {
+            TokenKind::Number(value)
+        })
+    }
+
+    /// Consumes characters matching `predicate` and returns the offset just past them.
+    fn eat_while(&mut self, predicate: impl Fn(char) -> bool) -> usize {
+        while self.chars.next_if(|(_, c)| predicate(*c)).is_some() {}
+        self.offset()
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.input.len(), |(i, _)| *i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(input: &str) -> Vec<TokenKind> {
+        tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_spacing_is_optional() {
+        let expected = vec![
+            TokenKind::Number(5.0),
+            TokenKind::Operator("+"),
+            TokenKind::Number(3.0),
+        ];
+        assert_eq!(kinds("5+3"), expected);
+        assert_eq!(kinds("5 +3"), expected);
+        assert_eq!(kinds("  5 + 3  "), expected);
+    }
+
+    #[test]
+    fn test_spans() {
+        let tokens = tokenize("12 *(x)").unwrap();
+        let spans: Vec<Span> = tokens.iter().map(|token| token.span).collect();
+        assert_eq!(
+            spans,
+            vec![
+                Span::new(0, 2),
+                Span::new(3, 4),
+                Span::new(4, 5),
+                Span::new(5, 6),
+                Span::new(6, 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identifiers() {
+        assert_eq!(
+            kinds("rate_2*x"),
+            vec![
+                TokenKind::Identifier("rate_2".to_string()),
+                TokenKind::Operator("*"),
+                TokenKind::Identifier("x".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exponent_numbers() {
+        assert_eq!(kinds("1.5e3"), vec![TokenKind::Number(1500.0)]);
+        assert_eq!(
+            kinds("2e-3-1"),
+            vec![
+                TokenKind::Number(0.002),
+                TokenKind::Operator("-"),
+                TokenKind::Number(1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_tokens() {
+        assert!(tokenize("3abc").is_err());
+        assert!(tokenize("1.2.3").is_err());
+        assert!(tokenize("5 & 3").is_err());
+    }
+
+    #[test]
+    fn test_remainder_operators() {
+        assert_eq!(
+            kinds("7%2//3 mod modulus"),
+            vec![
+                TokenKind::Number(7.0),
+                TokenKind::Operator("%"),
+                TokenKind::Number(2.0),
+                TokenKind::Operator("//"),
+                TokenKind::Number(3.0),
+                TokenKind::Operator("mod"),
+                TokenKind::Identifier("modulus".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_result_references() {
+        assert_eq!(
+            kinds("$1+$12"),
+            vec![
+                TokenKind::ResultRef(1),
+                TokenKind::Operator("+"),
+                TokenKind::ResultRef(12),
+            ]
+        );
+        assert_eq!(kinds("_"), vec![TokenKind::Identifier("_".to_string())]);
+        assert!(tokenize("$x").is_err());
+    }
+
+    #[test]
+    fn test_power_operators() {
+        assert_eq!(
+            kinds("2**3^4"),
+            vec![
+                TokenKind::Number(2.0),
+                TokenKind::Operator("**"),
+                TokenKind::Number(3.0),
+                TokenKind::Operator("^"),
+                TokenKind::Number(4.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_factorial_operator() {
+        assert_eq!(
+            kinds("5!!+1"),
+            vec![
+                TokenKind::Number(5.0),
+                TokenKind::Operator("!"),
+                TokenKind::Operator("!"),
+                TokenKind::Operator("+"),
+                TokenKind::Number(1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_imaginary_numbers() {
+        assert_eq!(
+            kinds("3+4i-1.5e2i*i"),
+            vec![
+                TokenKind::Number(3.0),
+                TokenKind::Operator("+"),
+                TokenKind::Imaginary(4.0),
+                TokenKind::Operator("-"),
+                TokenKind::Imaginary(150.0),
+                TokenKind::Operator("*"),
+                TokenKind::Identifier("i".to_string()),
+            ]
+        );
+        assert!(tokenize("4in").is_err());
+        assert!(tokenize("4i2").is_err());
+    }
+
+    #[test]
+    fn test_glued_units() {
+        assert_eq!(
+            kinds("5km/2h"),
+            vec![
+                TokenKind::Number(5.0),
+                TokenKind::Identifier("km".to_string()),
+                TokenKind::Operator("/"),
+                TokenKind::Number(2.0),
+                TokenKind::Identifier("h".to_string()),
+            ]
+        );
+        assert_eq!(
+            kinds("1e3mol"),
+            vec![
+                TokenKind::Number(1000.0),
+                TokenKind::Identifier("mol".to_string()),
+            ]
+        );
+        assert!(tokenize("5kmh").is_err());
+    }
+
+    #[test]
+    fn test_error_spans() {
+        assert_eq!(tokenize("1 + 3abc").unwrap_err().span(), Span::new(4, 8));
+        assert_eq!(tokenize("5 && 3").unwrap_err().span(), Span::new(2, 4));
+    }
+}
@@ -0,0 +1,235 @@
+//! The calculator engine behind the `rust_calculator` binary.
+//!
+//! Other crates can evaluate expressions without going through the REPL:
+//!
+//! ```
+//! use rust_calculator::Calculator;
+//!
+//! let mut calculator = Calculator::new();
+//! assert_eq!(calculator.evaluate("2 * (3 + 4)").unwrap(), Some(14.into()));
+//! assert_eq!(calculator.results(), &[14.into()]);
+//! ```
+//!
+//! Numbers are `f64`, or exact integers while every operand is whole,
+//! unless the session is switched to another [`Mode`]:
+//!
+//! ```
+//! use rust_calculator::{Calculator, Mode};
+//!
+//! let mut calculator = Calculator::new();
+//! calculator.environment_mut().set_mode(Mode::Decimal);
+//! let sum = calculator.evaluate("0.1 + 0.2").unwrap().unwrap();
+//! assert_eq!(sum.to_string(), "0.3");
+//!
+//! calculator.environment_mut().set_mode(Mode::Float);
+//! let factorial = calculator.evaluate("25!").unwrap().unwrap();
+//! assert_eq!(factorial.to_string(), "15511210043330985984000000");
+//! ```
+//!
+//! Parsing and evaluation are separate steps, so an expression can be
+//! inspected or printed before it is evaluated:
+//!
+//! ```
+//! use rust_calculator::{eval, parse, Expr};
+//!
+//! let expr = parse("1+2*3").unwrap();
+//! assert!(matches!(expr, Expr::Binary { .. }));
+//! assert_eq!(expr.to_string(), "1 + 2 * 3");
+//! assert_eq!(eval(&expr).unwrap(), 7.0);
+//! ```
+//!
+//! Errors are returned as [`CalcError`], which knows where in the input it
+//! happened and can be turned into a printable [`Diagnostic`].
+
+pub mod ast;
+pub mod commands;
+pub mod complex;
+pub mod constants;
+pub mod decimal;
+pub mod diagnostic;
+mod env;
+pub mod error;
+mod eval;
+pub mod functions;
+pub mod integer;
+pub mod json;
+pub mod lexer;
+pub mod number;
+mod parser;
+pub mod rational;
+pub mod script;
+pub mod units;
+
+pub use ast::{BinaryOp, Expr, Statement, UnaryOp};
+pub use diagnostic::Diagnostic;
+pub use env::{Environment, UserFunction};
+pub use error::CalcError;
+pub use eval::{eval, eval_with, exec_with};
+pub use lexer::Span;
+pub use number::{Mode, Number};
+pub use parser::{parse, parse_and_calculate, parse_partial, parse_statement, Parsed};
+
+/// A calculator session. Evaluating through a `Calculator` remembers every
+/// successful result, so later input can refer back to it as `ans` or `$n`:
+///
+/// ```
+/// let mut calculator = rust_calculator::Calculator::new();
+/// calculator.evaluate("10 + 5").unwrap();
+/// assert_eq!(calculator.evaluate("ans * 2").unwrap(), Some(30.into()));
+/// assert_eq!(calculator.evaluate("$1 + $2").unwrap(), Some(45.into()));
+///
+/// // Definitions have no result of their own
+/// assert_eq!(calculator.evaluate("f(x) = x^2").unwrap(), None);
+/// assert_eq!(calculator.evaluate("f($1)").unwrap(), Some(225.into()));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Calculator {
+    env: Environment,
+    /// The normalized input behind each entry of `env.results()`.
+    inputs: Vec<String>,
+}
+
+impl Calculator {
+    pub fn new() -> Calculator {
+        Calculator::default()
+    }
+
+    /// Parses and runs one line of input, which may be an expression, an
+    /// assignment or a function definition, recording the result on success.
+    pub fn evaluate(&mut self, input: &str) -> Result<Option<Number>, CalcError> {
+        let statement = parse_statement(input)?;
+        self.execute(&statement)
+    }
+
+    /// Evaluates an already parsed expression, recording the result on success.
+    pub fn evaluate_expr(&mut self, expr: &Expr) -> Result<Number, CalcError> {
+        let result = eval_with(expr, &self.env)?;
+        self.record(expr.to_string(), result.clone());
+        Ok(result)
+    }
+
+    /// Runs an already parsed statement, recording the result on success.
+    /// Assignments and definitions update this session's environment.
+    pub fn execute(&mut self, statement: &Statement) -> Result<Option<Number>, CalcError> {
+        let result = exec_with(statement, &mut self.env)?;
+        if let Some(value) = &result {
+            self.record(statement.to_string(), value.clone());
+        }
+        Ok(result)
+    }
+
+    fn record(&mut self, input: String, result: Number) {
+        self.inputs.push(input);
+        self.env.push_result(result);
+    }
+
+    /// Forgets every variable, function and result, but keeps settings such
+    /// as whether the physical constants are on and the mode.
+    pub fn reset(&mut self) {
+        let settings = &self.env;
+        let mut env = Environment::new();
+        env.set_physics(settings.physics_enabled());
+        env.set_mode(settings.mode());
+        env.set_decimal(settings.decimal());
+        env.set_fractions(settings.fractions());
+        env.set_complex(settings.complex());
+        *self = Calculator {
+            env,
+            inputs: Vec::new(),
+        };
+    }
+
+    /// The names and settings expressions are evaluated against.
+    pub fn environment(&self) -> &Environment {
+        &self.env
+    }
+
+    pub fn environment_mut(&mut self) -> &mut Environment {
+        &mut self.env
+    }
+
+    /// Every successful result of this session, oldest first.
+    pub fn results(&self) -> &[Number] {
+        self.env.results()
+    }
+
+    /// Every successful input of this session in normalized form, together
+    /// with its result and its `$n` number.
+    pub fn history(&self) -> impl Iterator<Item = (usize, &str, &Number)> + '_ {
+        self.inputs
+            .iter()
+            .zip(self.env.results())
+            .enumerate()
+            .map(|(i, (input, result))| (i + 1, input.as_str(), result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calculator_records_results() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.evaluate("5 + 3").unwrap(), Some(8.into()));
+        assert_eq!(calculator.evaluate("2 * 3").unwrap(), Some(6.into()));
+        assert_eq!(calculator.results(), &[8.into(), 6.into()]);
+    }
+
+    #[test]
+    fn test_calculator_names() {
+        let mut calculator = Calculator::new();
+        calculator.evaluate("rate = 0.5").unwrap();
+        calculator.evaluate("area(r) = pi * r^2").unwrap();
+        let names = calculator.environment().names();
+        for name in ["sqrt", "if", "area", "pi", "rate", "ans", "_", "mph"] {
+            assert!(names.contains(&name), "{} is missing", name);
+        }
+        assert!(!names.contains(&"c"));
+        assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+
+        calculator.environment_mut().set_physics(true);
+        assert!(calculator.environment().names().contains(&"c"));
+    }
+
+    #[test]
+    fn test_calculator_physics_setting() {
+        let mut calculator = Calculator::new();
+        assert!(calculator.evaluate("h").is_err());
+        calculator.environment_mut().set_physics(true);
+        assert_eq!(
+            calculator.evaluate("h").unwrap(),
+            Some(6.626_070_15e-34.into())
+        );
+    }
+
+    #[test]
+    fn test_calculator_variables() {
+        let mut calculator = Calculator::new();
+        calculator.evaluate("let rate = 0.5").unwrap();
+        assert_eq!(calculator.evaluate("rate * 10").unwrap(), Some(5.0.into()));
+        assert_eq!(calculator.environment().variable("rate"), Some(&0.5.into()));
+    }
+
+    #[test]
+    fn test_calculator_history() {
+        let mut calculator = Calculator::new();
+        calculator.evaluate("5+3").unwrap();
+        calculator.evaluate("let x = ans*2").unwrap();
+        calculator.evaluate("f(x) = x").unwrap();
+        assert!(calculator.evaluate("$9").is_err());
+        let history: Vec<_> = calculator.history().collect();
+        let (eight, sixteen) = (Number::from(8), Number::from(16));
+        assert_eq!(
+            history,
+            vec![(1, "5 + 3", &eight), (2, "x = ans * 2", &sixteen)]
+        );
+    }
+
+    #[test]
+    fn test_calculator_skips_errors() {
+        let mut calculator = Calculator::new();
+        assert!(calculator.evaluate("10 / 0").is_err());
+        assert!(calculator.results().is_empty());
+    }
+}
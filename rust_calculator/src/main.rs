@@ -1,68 +1,470 @@
+use std::env;
+use std::fs;
 use std::io;
+use std::process::ExitCode;
 
-fn main() {
-    println!("🦀 Welcome to Rust Calculator!");
-    println!("Enter calculations like: 5 + 3 or type 'quit' to exit");
+use rust_calculator::commands::{Action, Commands};
+use rust_calculator::complex::ComplexStyle;
+use rust_calculator::decimal::{self, Rounding};
+use rust_calculator::json::Evaluation;
+use rust_calculator::rational::FractionStyle;
+use rust_calculator::{
+    parse_partial, parse_statement, script, CalcError, Calculator, Mode, Number, Parsed, Statement,
+};
+
+use crate::editor::Input;
+
+mod editor;
+
+/// Exit status when an expression evaluates but the math fails, e.g. `1 / 0`.
+const EXIT_MATH_ERROR: u8 = 1;
+/// Exit status when an expression cannot be parsed, e.g. `2 +`.
+const EXIT_PARSE_ERROR: u8 = 2;
+/// Exit status for unknown options, missing option values and settings
+/// commands in scripts that fail, e.g. `:mode fast`.
+const EXIT_USAGE: u8 = 64;
+/// Exit status when a script file cannot be read.
+const EXIT_NO_INPUT: u8 = 66;
+
+const USAGE: &str = "\
+Usage: rust_calculator [OPTIONS] [EXPRESSION...]
+       rust_calculator run <FILE>
+
+Evaluates EXPRESSION and prints the result. The words of EXPRESSION are
+joined with spaces, so quoting is optional for simple input. Without an
+expression, starts the interactive calculator, or reads one calculation per
+line when input is piped in.
+
+`run` executes a script: one statement per line or separated by `;`, with
+`#` comments and `\\` at the end of a line to continue it on the next. The
+result of each bare expression is printed, and lines such as `:mode decimal`
+change the settings as they would in the interactive calculator.
+
+Options:
+  -e, --expr <EXPR>    Evaluate EXPR; repeat to run several in order,
+                       sharing variables and functions
+  -o, --output <KIND>  Print results as `text` (the default) or `json`, one
+                       JSON object per evaluation and line (JSON Lines)
+  -m, --mode <MODE>    Calculate with `float` numbers (the default, exact
+                       for whole numbers such as 2^200 and 30!), with
+                       `decimal` ones, so that 0.1 + 0.2 is exactly 0.3,
+                       with `rational` ones, so that 1/3 + 1/6 is 1/2,
+                       with `int` ones, where 7 / 2 is an error, or with
+                       `complex` ones, where sqrt(-1) is i
+      --precision <N>  Significant digits of decimal results (default 28)
+      --rounding <HOW> How decimal results are rounded: half-even (the
+                       default), half-up, half-down, up, down, ceiling or
+                       floor
+      --fractions <STYLE>
+                       Print rational results as `improper` fractions like
+                       3/2 (the default), `mixed` numbers like 1 1/2 or
+                       `decimal` approximations like 1.5
+      --complex <STYLE>
+                       Print complex results in `rectangular` form like
+                       3+4i (the default) or `polar` form like
+                       5 * e^(0.93i)
+  -h, --help           Print this help and exit
+  -V, --version        Print the version and exit
+  --                   Treat everything after this as the expression
+
+Exit status: 0 on success, 1 for math errors, 2 for parse errors, 64 for
+invalid options and 66 for unreadable scripts.";
+
+/// How results and errors are printed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Output {
+    /// Results on stdout, rustc-style diagnostics for errors.
+    Text,
+    /// One JSON object per evaluation on stdout, errors included.
+    Json,
+}
+
+/// Settings from the command line that apply to whatever runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Options {
+    output: Output,
+    mode: Mode,
+    decimal: decimal::Settings,
+    fractions: FractionStyle,
+    complex: ComplexStyle,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            output: Output::Text,
+            mode: Mode::default(),
+            decimal: decimal::Settings::default(),
+            fractions: FractionStyle::default(),
+            complex: ComplexStyle::default(),
+        }
+    }
+}
+
+impl Options {
+    /// A new session with these settings.
+    fn calculator(&self) -> Calculator {
+        let mut calculator = Calculator::new();
+        let env = calculator.environment_mut();
+        env.set_mode(self.mode);
+        env.set_decimal(self.decimal);
+        env.set_fractions(self.fractions);
+        env.set_complex(self.complex);
+        calculator
+    }
+}
+
+/// What the command line asks for.
+#[derive(Debug, PartialEq)]
+enum Command {
+    Repl,
+    Evaluate(Vec<String>),
+    Run(String),
+    Help,
+    Version,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match parse_args(&args) {
+        Ok((Command::Repl, options)) => repl(options.calculator(), options.output),
+        Ok((Command::Evaluate(expressions), options)) => {
+            evaluate_all(&expressions, options.calculator(), options.output)
+        }
+        Ok((Command::Run(path), options)) => {
+            run_script(&path, options.calculator(), options.output)
+        }
+        Ok((Command::Help, _)) => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        Ok((Command::Version, _)) => {
+            println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            ExitCode::SUCCESS
+        }
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            ExitCode::from(EXIT_USAGE)
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<(Command, Options), String> {
+    let mut options = Options::default();
+    let mut script = None;
+    let mut expressions = Vec::new();
+    let mut words: Vec<&str> = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        // `--name=value` is the same as `--name value`
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || inline.or_else(|| args.next().map(String::as_str));
+        match name {
+            "-h" | "--help" => return Ok((Command::Help, options)),
+            "-V" | "--version" => return Ok((Command::Version, options)),
+            "-e" | "--expr" => match value() {
+                Some(expression) => expressions.push(expression.to_string()),
+                None => return Err(format!("{} needs an expression", name)),
+            },
+            "-o" | "--output" => options.output = parse_output(value())?,
+            "-m" | "--mode" => match value() {
+                Some(mode) => options.mode = mode.parse()?,
+                None => return Err(format!("{} needs one of {}", name, Mode::NAMES.join(", "))),
+            },
+            "--precision" => {
+                let digits = value().ok_or("--precision needs a number of digits")?;
+                let digits = digits.parse().map_err(|_| {
+                    format!(
+                        "invalid precision '{}', expected a number of digits",
+                        digits
+                    )
+                })?;
+                options.decimal = options.decimal.with_precision(digits)?;
+            }
+            "--rounding" => {
+                let rounding = value().ok_or("--rounding needs a rounding mode")?;
+                options.decimal = options.decimal.with_rounding(rounding.parse::<Rounding>()?);
+            }
+            "--fractions" => {
+                let style = value().ok_or("--fractions needs a style")?;
+                options.fractions = style.parse()?;
+            }
+            "--complex" => {
+                let style = value().ok_or("--complex needs a style")?;
+                options.complex = style.parse()?;
+            }
+            // `run` is only a subcommand in front of everything else
+            "run" if script.is_none() && expressions.is_empty() && words.is_empty() => {
+                match args.next() {
+                    Some(path) if !path.starts_with('-') => script = Some(path.clone()),
+                    _ => return Err("run needs a script file".to_string()),
+                }
+            }
+            "--" => words.extend(args.by_ref().map(String::as_str)),
+            _ if is_option(arg) => return Err(format!("unknown option '{}'", name)),
+            _ => words.push(arg),
+        }
+    }
+    if !words.is_empty() {
+        expressions.push(words.join(" "));
+    }
+    let command = match script {
+        Some(_) if !expressions.is_empty() => {
+            return Err("run takes one script file and no expressions".to_string())
+        }
+        Some(path) => Command::Run(path),
+        None if expressions.is_empty() => Command::Repl,
+        None => Command::Evaluate(expressions),
+    };
+    Ok((command, options))
+}
+
+fn parse_output(kind: Option<&str>) -> Result<Output, String> {
+    match kind {
+        Some("text") => Ok(Output::Text),
+        Some("json") => Ok(Output::Json),
+        Some(other) => Err(format!("unknown output '{}', expected text or json", other)),
+        None => Err("--output needs text or json".to_string()),
+    }
+}
+
+/// Whether an argument that is not a known flag is an unknown option rather
+/// than part of an expression; `-pi` and `-sqrt(4)` are expressions, `--x`
+/// and `-x:` are not.
+fn is_option(arg: &str) -> bool {
+    arg.starts_with("--") || (arg.starts_with('-') && parse_statement(arg).is_err())
+}
+
+/// Evaluates each expression in turn, printing one result per line, and
+/// stops at the first error.
+fn evaluate_all(expressions: &[String], mut calculator: Calculator, output: Output) -> ExitCode {
+    for input in expressions {
+        let outcome = execute(&mut calculator, input);
+        if output == Output::Json {
+            println!("{}", Evaluation::new(input, &json_result(&outcome)));
+        }
+        match outcome {
+            Ok((_, Some(result))) if output == Output::Text => {
+                println!("{}", calculator.environment().format(&result))
+            }
+            Ok(_) => {}
+            Err((error, status)) => {
+                if output == Output::Text {
+                    eprintln!("{}", error.diagnostic().render(input));
+                }
+                return status;
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Runs a script file, printing the result of each bare expression, and
+/// stops at the first error.
+fn run_script(path: &str, mut calculator: Calculator, output: Output) -> ExitCode {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("error: cannot read {}: {}", path, error);
+            return ExitCode::from(EXIT_NO_INPUT);
+        }
+    };
+    let commands = Commands::new();
+    for chunk in script::split(&source) {
+        // Settings such as `:mode decimal`, which `:save` also writes, and
+        // the other commands the REPL knows, like `:vars` and `quit`
+        if let Some(outcome) = commands.run(&mut calculator, &chunk.text) {
+            match outcome {
+                Ok(Action::Print(text)) => {
+                    if output == Output::Text && !text.is_empty() {
+                        println!("{}", text);
+                    }
+                }
+                Ok(Action::ClearScreen) => {}
+                Ok(Action::Quit) => return ExitCode::SUCCESS,
+                Err(message) => {
+                    eprintln!("{}:{}: {}", path, chunk.line, message);
+                    return ExitCode::from(EXIT_USAGE);
+                }
+            }
+            continue;
+        }
+        let outcome = execute(&mut calculator, &chunk.text);
+        if output == Output::Json {
+            let result = json_result(&outcome);
+            let evaluation = Evaluation::new(&chunk.text, &result).with_line(chunk.line);
+            println!("{}", evaluation);
+        }
+        match outcome {
+            Ok((Statement::Expr(_), Some(result))) if output == Output::Text => {
+                println!("{}", calculator.environment().format(&result))
+            }
+            Ok(_) => {}
+            Err((error, status)) => {
+                if output == Output::Text {
+                    let mut diagnostic = error.diagnostic();
+                    diagnostic.span = chunk.locate(diagnostic.span);
+                    eprintln!("{}", diagnostic.render_in_file(path, &source));
+                }
+                return status;
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Parses and runs one statement. Errors come with the exit status for
+/// their kind.
+fn execute(
+    calculator: &mut Calculator,
+    input: &str,
+) -> Result<(Statement, Option<Number>), (CalcError, ExitCode)> {
+    let statement = parse_statement(input)
+        .map_err(|error| (error, ExitCode::from(EXIT_PARSE_ERROR)))?;
+    match calculator.execute(&statement) {
+        Ok(result) => Ok((statement, result)),
+        Err(error) => Err((error, ExitCode::from(EXIT_MATH_ERROR))),
+    }
+}
+
+/// Drops the parts of an `execute` outcome that JSON output leaves out.
+fn json_result(
+    outcome: &Result<(Statement, Option<Number>), (CalcError, ExitCode)>,
+) -> Result<Option<Number>, CalcError> {
+    match outcome {
+        Ok((_, result)) => Ok(result.clone()),
+        Err((error, _)) => Err(error.clone()),
+    }
+}
+
+fn repl(mut calculator: Calculator, output: Output) -> ExitCode {
+    // When input is piped in, skip the banner and prompts and print one bare
+    // result per line, so the output can feed other commands
+    let commands = Commands::new();
+    let mut reader = Input::new(commands.names().collect());
+    let interactive = reader.is_interactive();
+    if interactive {
+        println!("🦀 Welcome to Rust Calculator!");
+        println!("Enter calculations like: 5 + 3, (2 + 3) * 4 or sqrt(16)");
+        println!("Type ':help' for more, or 'quit' to exit");
+    }
+    
+    // Lines of a calculation that is still incomplete, like `(1 +`
+    let mut pending = String::new();
     
     loop {
-        println!("\nEnter your calculation:");
+        if interactive && pending.is_empty() {
+            println!("\nEnter your calculation:");
+        }
         
-        let mut input = String::new();
-        io::stdin()
-            .read_line(&mut input)
-            .expect("Failed to read line");
+        let prompt = if pending.is_empty() { "" } else { "... " };
+        let names = calculator.environment().names().into_iter().map(String::from).collect();
+        let line = match reader.read_line(prompt, names) {
+            Ok(Some(line)) => line,
+            // End of input: Ctrl-D at the prompt or the end of a pipe
+            Ok(None) => {
+                if !pending.is_empty() {
+                    evaluate_line(&mut calculator, &pending, output, interactive);
+                }
+                if interactive {
+                    println!("Goodbye! 👋");
+                }
+                break;
+            }
+            // Ctrl-C abandons the calculation being typed, like in a shell
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {
+                pending.clear();
+                continue;
+            }
+            Err(error) => {
+                eprintln!("Failed to read input: {}", error);
+                return ExitCode::FAILURE;
+            }
+        };
         
-        let input = input.trim();
+        let line = line.trim();
         
-        // Check if user wants to quit
-        if input.to_lowercase() == "quit" {
-            println!("Goodbye! 👋");
-            break;
+        // Keep reading while the calculation is incomplete; a blank line
+        // gives up and shows what is missing
+        if !pending.is_empty() {
+            if !line.is_empty() {
+                pending = format!("{} {}", pending, line);
+                if let Parsed::Incomplete(_) = parse_partial(&pending) {
+                    continue;
+                }
+            }
+            let input = std::mem::take(&mut pending);
+            evaluate_line(&mut calculator, &input, output, interactive);
+            continue;
+        }
+        
+        if line.is_empty() {
+            continue;
+        }
+        let input = line;
+        
+        // Colon commands such as :help, plus quit and exit
+        if let Some(outcome) = commands.run(&mut calculator, input) {
+            match outcome {
+                Ok(Action::Print(text)) => {
+                    if !text.is_empty() {
+                        println!("{}", text);
+                    }
+                }
+                Ok(Action::ClearScreen) => print!("\x1b[2J\x1b[H"),
+                Ok(Action::Quit) => {
+                    if interactive {
+                        println!("Goodbye! 👋");
+                    }
+                    break;
+                }
+                Err(message) => println!("{}", message),
+            }
+            continue;
         }
         
-        // Parse and calculate
-        match parse_and_calculate(input) {
-            Ok(result) => println!("Result: {}", result),
-            Err(error) => println!("Error: {}", error),
+        // Only someone typing can finish a calculation on the next line;
+        // piped input gets one result or error per line
+        if interactive && matches!(parse_partial(input), Parsed::Incomplete(_)) {
+            pending = input.to_string();
+            continue;
         }
+        evaluate_line(&mut calculator, input, output, interactive);
     }
+    if let Err(error) = reader.save_history() {
+        eprintln!("Could not save history: {}", error);
+    }
+    ExitCode::SUCCESS
 }
 
-fn parse_and_calculate(input: &str) -> Result<f64, String> {
-    // Split input by spaces
-    let parts: Vec<&str> = input.split_whitespace().collect();
-    
-    // Check if we have exactly 3 parts: number operator number
-    if parts.len() != 3 {
-        return Err("Format should be: number operator number".to_string());
+/// Parses and runs one calculation typed into the REPL and shows the
+/// outcome.
+fn evaluate_line(calculator: &mut Calculator, input: &str, output: Output, interactive: bool) {
+    let outcome = execute(calculator, input);
+    if output == Output::Json {
+        println!("{}", Evaluation::new(input, &json_result(&outcome)));
+        return;
     }
-    
-    // Parse first number
-    let num1: f64 = parts[0]
-        .parse()
-        .map_err(|_| format!("'{}' is not a valid number", parts[0]))?;
-    
-    // Get operator
-    let operator = parts[1];
-    
-    // Parse second number  
-    let num2: f64 = parts[2]
-        .parse()
-        .map_err(|_| format!("'{}' is not a valid number", parts[2]))?;
-    
-    // Perform calculation based on operator
-    match operator {
-        "+" => Ok(num1 + num2),
-        "-" => Ok(num1 - num2),
-        "*" => Ok(num1 * num2),
-        "/" => {
-            if num2 == 0.0 {
-                Err("Cannot divide by zero!".to_string())
-            } else {
-                Ok(num1 / num2)
-            }
+    let env = calculator.environment();
+    match outcome {
+        Ok((_, Some(result))) if !interactive => println!("{}", env.format(&result)),
+        Ok((Statement::Assign { name, .. }, Some(result))) => {
+            println!("{} = {}", name, env.format(&result))
+        }
+        Ok((Statement::Expr(_), Some(result))) => {
+            println!(
+                "Result: {}  (${})",
+                env.format(&result),
+                calculator.results().len()
+            )
         }
-        _ => Err(format!("Unsupported operator: {}", operator)),
+        Ok((_, None)) if !interactive => {}
+        Ok((statement, _)) => println!("Defined {}", statement),
+        Err((error, _)) if !interactive => eprintln!("{}", error.diagnostic().render(input)),
+        Err((error, _)) => println!("{}", error.diagnostic().render(input)),
     }
 }
 
@@ -70,44 +472,144 @@ fn parse_and_calculate(input: &str) -> Result<f64, String> {
 mod tests {
     use super::*;
 
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_args(&args).map(|(command, _)| command)
+    }
+
+    fn output(args: &[&str]) -> Result<Output, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_args(&args).map(|(_, options)| options.output)
+    }
+
+    fn evaluate(expressions: &[&str]) -> Command {
+        Command::Evaluate(expressions.iter().map(|e| e.to_string()).collect())
+    }
+
     #[test]
-    fn test_addition() {
-        assert_eq!(parse_and_calculate("5 + 3").unwrap(), 8.0);
+    fn test_no_arguments_starts_the_repl() {
+        assert_eq!(parse(&[]), Ok(Command::Repl));
     }
 
     #[test]
-    fn test_subtraction() {
-        assert_eq!(parse_and_calculate("10 - 4").unwrap(), 6.0);
+    fn test_positional_words_form_one_expression() {
+        assert_eq!(parse(&["2 * (3 + 4)"]), Ok(evaluate(&["2 * (3 + 4)"])));
+        assert_eq!(parse(&["2", "+", "3"]), Ok(evaluate(&["2 + 3"])));
+        assert_eq!(parse(&["-5", "+", "3"]), Ok(evaluate(&["-5 + 3"])));
+        assert_eq!(parse(&["--", "-pi"]), Ok(evaluate(&["-pi"])));
+        assert_eq!(parse(&["-pi"]), Ok(evaluate(&["-pi"])));
+        assert_eq!(
+            parse(&["-sqrt(4)", "*", "2"]),
+            Ok(evaluate(&["-sqrt(4) * 2"]))
+        );
+        assert_eq!(parse(&["-m", "integer", "-x"]), Ok(evaluate(&["-x"])));
     }
 
     #[test]
-    fn test_multiplication() {
-        assert_eq!(parse_and_calculate("3 * 7").unwrap(), 21.0);
+    fn test_expr_options() {
+        assert_eq!(
+            parse(&["-e", "x = 2", "--expr", "x * 3", "--expr=x^2", "x", "+", "1"]),
+            Ok(evaluate(&["x = 2", "x * 3", "x^2", "x + 1"]))
+        );
+        assert!(parse(&["-e"]).is_err());
     }
 
     #[test]
-    fn test_division() {
-        assert_eq!(parse_and_calculate("15 / 3").unwrap(), 5.0);
+    fn test_run_scripts() {
+        assert_eq!(parse(&["run", "budget.calc"]), Ok(Command::Run("budget.calc".to_string())));
+        assert!(parse(&["run"]).is_err());
+        assert!(parse(&["run", "a.calc", "b.calc"]).is_err());
+        assert!(parse(&["run", "a.calc", "-e", "1"]).is_err());
+        assert_eq!(parse(&["-o", "json", "run", "a.calc"]), Ok(Command::Run("a.calc".to_string())));
+        // Only a leading `run` is a subcommand
+        assert_eq!(parse(&["2", "run"]), Ok(evaluate(&["2 run"])));
     }
 
     #[test]
-    fn test_division_by_zero() {
-        assert!(parse_and_calculate("10 / 0").is_err());
+    fn test_scripts_run_commands_and_stop_at_quit() {
+        let path =
+            std::env::temp_dir().join(format!("rust_calculator_{}.calc", std::process::id()));
+        let path = path.to_str().unwrap();
+        let run = |source: &str| {
+            fs::write(path, source).unwrap();
+            run_script(path, Calculator::new(), Output::Text)
+        };
+        assert_eq!(
+            run("x = 2\n:vars\n:help\nundefined_name\n"),
+            ExitCode::from(EXIT_MATH_ERROR)
+        );
+        assert_eq!(
+            run("x = 2\n:vars\nquit\nundefined_name\n"),
+            ExitCode::SUCCESS
+        );
+        assert_eq!(run("x = 2\n:exit\nundefined_name\n"), ExitCode::SUCCESS);
+        assert_eq!(run("quit = 3\nquit + 1\n"), ExitCode::SUCCESS);
+        assert_eq!(run(":nonsense\n"), ExitCode::from(EXIT_USAGE));
+        fs::remove_file(path).unwrap();
     }
 
     #[test]
-    fn test_invalid_operator() {
-        assert!(parse_and_calculate("5 % 3").is_err());
+    fn test_output_option() {
+        assert_eq!(output(&["1"]), Ok(Output::Text));
+        assert_eq!(output(&["--output", "json", "1"]), Ok(Output::Json));
+        assert_eq!(output(&["run", "a.calc", "--output=json"]), Ok(Output::Json));
+        assert_eq!(output(&["-o", "json", "-o", "text"]), Ok(Output::Text));
+        assert!(output(&["--output", "xml"]).is_err());
+        assert!(output(&["--output"]).is_err());
     }
 
     #[test]
-    fn test_invalid_number() {
-        assert!(parse_and_calculate("abc + 3").is_err());
+    fn test_mode_options() {
+        let options = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            parse_args(&args).map(|(_, options)| options)
+        };
+        assert_eq!(options(&["1"]).unwrap().mode, Mode::Float);
+        assert_eq!(options(&["--mode", "decimal", "1"]).unwrap().mode, Mode::Decimal);
+        let decimal = options(&["-m", "decimal", "--precision=50", "--rounding", "half-up"])
+            .unwrap()
+            .decimal;
+        assert_eq!(decimal.precision(), 50);
+        assert_eq!(decimal.rounding(), Rounding::HalfUp);
+        assert!(options(&["--mode", "exact"]).is_err());
+        assert!(options(&["--mode"]).is_err());
+        assert!(options(&["--precision", "0"]).is_err());
+        assert!(options(&["--precision", "many"]).is_err());
+        assert!(options(&["--rounding", "sideways"]).is_err());
+
+        let mut calculator = options(&["--mode=decimal"]).unwrap().calculator();
+        let sum = calculator.evaluate("0.1 + 0.2").unwrap().unwrap();
+        assert_eq!(sum.to_string(), "0.3");
+
+        let calculator = options(&["-m", "rational", "--fractions", "mixed"])
+            .unwrap()
+            .calculator();
+        assert_eq!(calculator.environment().mode(), Mode::Rational);
+        assert_eq!(calculator.environment().fractions(), FractionStyle::Mixed);
+        assert!(options(&["--fractions", "proper"]).is_err());
+        assert!(options(&["--fractions"]).is_err());
+
+        let mut calculator = options(&["-m", "complex", "--complex", "polar"])
+            .unwrap()
+            .calculator();
+        assert_eq!(calculator.environment().complex(), ComplexStyle::Polar);
+        let root = calculator.evaluate("sqrt(-9)").unwrap().unwrap();
+        assert_eq!(
+            calculator.environment().format(&root),
+            "3 * e^(1.5707963267948966i)"
+        );
+        assert!(options(&["--complex", "cartesian"]).is_err());
     }
 
     #[test]
-    fn test_wrong_format() {
-        assert!(parse_and_calculate("5 +").is_err());
-        assert!(parse_and_calculate("5 + 3 + 2").is_err());
+    fn test_help_version_and_unknown_options() {
+        assert_eq!(parse(&["1", "--help"]), Ok(Command::Help));
+        assert_eq!(parse(&["-V"]), Ok(Command::Version));
+        assert_eq!(parse(&["--verbose"]), Err("unknown option '--verbose'".to_string()));
+        assert_eq!(parse(&["-v:"]), Err("unknown option '-v:'".to_string()));
+        assert_eq!(
+            parse(&["run", "-e"]),
+            Err("run needs a script file".to_string())
+        );
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,267 @@
+use std::fmt;
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::Zero;
+
+use crate::complex::{self, ComplexStyle};
+use crate::units::Quantity;
+use crate::{decimal, integer, rational};
+
+/// A value produced by evaluation.
+///
+/// Which kind of number a calculation produces depends on the session's
+/// [`Mode`]. Values of another kind, such as variables stored before the
+/// mode was switched, are converted when they are used.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Number {
+    Float(f64),
+    /// A whole number of any size, used while every operand is whole in
+    /// float and int mode.
+    Integer(BigInt),
+    /// An exact base-10 number. Infinity and NaN have no decimal form, so
+    /// they stay [`Number::Float`] even in decimal mode.
+    Decimal(BigDecimal),
+    /// An exact fraction in lowest terms.
+    Rational(BigRational),
+    /// A complex number. Calculations only produce one when its imaginary
+    /// part is not zero.
+    Complex(Complex64),
+    /// A number with a unit, such as `5.3 km`. Calculations only produce
+    /// one when the unit has not cancelled out.
+    Quantity(Quantity),
+}
+
+impl Number {
+    /// The nearest `f64`, which may lose digits of a decimal or fraction.
+    /// Complex numbers have none, so they are NaN, and quantities give the
+    /// number in their own unit.
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Float(value) => *value,
+            Number::Integer(value) => integer::to_f64(value),
+            Number::Decimal(value) => decimal::to_f64(value),
+            Number::Rational(value) => rational::to_f64(value),
+            Number::Complex(value) if value.im == 0.0 => value.re,
+            Number::Complex(_) => f64::NAN,
+            Number::Quantity(quantity) => quantity.value,
+        }
+    }
+
+    /// The value as a complex number, which is exact for floats.
+    pub fn to_complex(&self) -> Complex64 {
+        match self {
+            Number::Complex(value) => *value,
+            number => number.to_f64().into(),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Float(value) => *value == 0.0,
+            Number::Integer(value) => value.is_zero(),
+            Number::Decimal(value) => value.is_zero(),
+            Number::Rational(value) => value.is_zero(),
+            Number::Complex(value) => value.is_zero(),
+            Number::Quantity(quantity) => quantity.value == 0.0,
+        }
+    }
+
+    /// The value as an integer, if it is a whole number of any kind without
+    /// a unit.
+    pub fn to_integer(&self) -> Option<BigInt> {
+        match self {
+            Number::Float(value) => integer::from_f64(*value),
+            Number::Integer(value) => Some(value.clone()),
+            // Through a fraction, which refuses exponents like `1e999999999`
+            Number::Decimal(value) => {
+                let value = rational::from_decimal(value)?;
+                value.is_integer().then(|| value.to_integer())
+            }
+            Number::Rational(value) => value.is_integer().then(|| value.to_integer()),
+            Number::Complex(value) if value.im == 0.0 => integer::from_f64(value.re),
+            Number::Complex(_) | Number::Quantity(_) => None,
+        }
+    }
+}
+
+impl From<f64> for Number {
+    fn from(value: f64) -> Number {
+        Number::Float(value)
+    }
+}
+
+impl From<BigInt> for Number {
+    fn from(value: BigInt) -> Number {
+        Number::Integer(value)
+    }
+}
+
+impl From<i64> for Number {
+    fn from(value: i64) -> Number {
+        Number::Integer(value.into())
+    }
+}
+
+impl From<BigDecimal> for Number {
+    fn from(value: BigDecimal) -> Number {
+        Number::Decimal(value)
+    }
+}
+
+impl From<BigRational> for Number {
+    fn from(value: BigRational) -> Number {
+        Number::Rational(value)
+    }
+}
+
+impl From<Complex64> for Number {
+    fn from(value: Complex64) -> Number {
+        Number::Complex(value)
+    }
+}
+
+impl From<Quantity> for Number {
+    fn from(value: Quantity) -> Number {
+        Number::Quantity(value)
+    }
+}
+
+impl fmt::Display for Number {
+    /// Floats print as the shortest text that reads back as the same value,
+    /// decimals without trailing zeros: `0.1 + 0.2` is `0.3`, not `0.30`.
+    /// Fractions print as improper fractions such as `3/2`, which read back
+    /// as the same value in rational mode, complex numbers in rectangular
+    /// form such as `3-4i`, and quantities with their unit, as in `5.3 km`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Float(value) => write!(f, "{}", value),
+            Number::Integer(value) => write!(f, "{}", value),
+            Number::Decimal(value) => write!(f, "{}", decimal::to_plain_string(value)),
+            Number::Rational(value) if value.is_integer() => write!(f, "{}", value.numer()),
+            Number::Rational(value) => write!(f, "{}/{}", value.numer(), value.denom()),
+            Number::Complex(value) => {
+                write!(f, "{}", complex::format(value, ComplexStyle::Rectangular))
+            }
+            Number::Quantity(quantity) => write!(f, "{}", quantity),
+        }
+    }
+}
+
+/// The kind of number that calculations produce.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Binary floating point: fast, about 16 significant digits, and
+    /// `0.1 + 0.2` is `0.30000000000000004`. Whole numbers written without
+    /// a point or exponent stay exact integers until a calculation leaves
+    /// the integers, so `2^200` and `30!` print every digit.
+    #[default]
+    Float,
+    /// Base-10 arithmetic with the session's precision and rounding, so
+    /// amounts of money add up exactly.
+    Decimal,
+    /// Exact fractions, so `1/3 + 1/6` is `1/2`.
+    Rational,
+    /// Exact integers of any size, where `7 / 2` is an error rather than
+    /// `3.5`. Values that are not whole, like `sqrt(2)`, are floats.
+    Integer,
+    /// Like float mode, but real arguments outside a function's domain
+    /// give complex results, so `sqrt(-1)` is `i` rather than an error.
+    Complex,
+}
+
+impl Mode {
+    /// Every mode's name, as `--mode` and `:mode` accept them.
+    pub const NAMES: &'static [&'static str] = &["float", "decimal", "rational", "int", "complex"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Float => "float",
+            Mode::Decimal => "decimal",
+            Mode::Rational => "rational",
+            Mode::Integer => "int",
+            Mode::Complex => "complex",
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Mode, String> {
+        match name.to_lowercase().as_str() {
+            "float" => Ok(Mode::Float),
+            "decimal" => Ok(Mode::Decimal),
+            "rational" => Ok(Mode::Rational),
+            "int" | "integer" => Ok(Mode::Integer),
+            "complex" => Ok(Mode::Complex),
+            _ => Err(format!(
+                "unknown mode '{}', expected one of {}",
+                name,
+                Mode::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Unit;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Number::from(0.5).to_string(), "0.5");
+        assert_eq!(Number::from(f64::NEG_INFINITY).to_string(), "-inf");
+        let decimal: BigDecimal = "1.2500".parse().unwrap();
+        assert_eq!(Number::from(decimal).to_string(), "1.25");
+        let decimal: BigDecimal = "1.2E+3".parse().unwrap();
+        assert_eq!(Number::from(decimal).to_string(), "1200");
+        let fraction = BigRational::new((-6).into(), 4.into());
+        assert_eq!(Number::from(fraction).to_string(), "-3/2");
+        let fraction = BigRational::new(6.into(), 3.into());
+        assert_eq!(Number::from(fraction).to_string(), "2");
+        let big = BigInt::from(2).pow(70);
+        assert_eq!(Number::from(big).to_string(), "1180591620717411303424");
+        let complex = Complex64::new(1.5, -2.0);
+        assert_eq!(Number::from(complex).to_string(), "1.5-2i");
+        let distance = Quantity::new(2.5, Unit::lookup("km").unwrap());
+        assert_eq!(Number::from(distance).to_string(), "2.5 km");
+    }
+
+    #[test]
+    fn test_to_integer() {
+        assert_eq!(Number::from(-4.0).to_integer(), Some(BigInt::from(-4)));
+        assert_eq!(Number::from(4.5).to_integer(), None);
+        let decimal: BigDecimal = "1.2E+3".parse().unwrap();
+        assert_eq!(Number::from(decimal).to_integer(), Some(BigInt::from(1200)));
+        let fraction = BigRational::new(1.into(), 3.into());
+        assert_eq!(Number::from(fraction).to_integer(), None);
+        assert_eq!(Number::from(Complex64::i()).to_integer(), None);
+    }
+
+    #[test]
+    fn test_mode_names() {
+        for name in Mode::NAMES {
+            assert_eq!(name.parse::<Mode>().unwrap().name(), *name);
+        }
+        assert_eq!("Decimal".parse(), Ok(Mode::Decimal));
+        assert_eq!("integer".parse(), Ok(Mode::Integer));
+        assert_eq!(
+            "fixed".parse::<Mode>(),
+            Err(
+                "unknown mode 'fixed', expected one of float, decimal, rational, int, complex"
+                    .to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,987 @@
+use crate::ast::{BinaryOp, Expr, Statement, UnaryOp};
+use crate::error::{closest_match, is_operator_typo, CalcError};
+use crate::eval::eval;
+use crate::lexer::{tokenize, Span, Token, TokenKind};
+use crate::units::{self, Unit};
+
+/// Parses and evaluates a single expression such as `2 * (3 + 4)`.
+/// Quantities are given in SI base units, as by [`eval`].
+pub fn parse_and_calculate(input: &str) -> Result<f64, CalcError> {
+    eval(&parse(input)?)
+}
+
+/// Parses a single expression into an [`Expr`] without evaluating it.
+pub fn parse(input: &str) -> Result<Expr, CalcError> {
+    let mut parser = Parser::new(input)?;
+    let expr = parser.parse_expression(0)?;
+    parser.expect_end()?;
+    Ok(expr)
+}
+
+/// Parses one line of input, which is either an expression, an assignment
+/// such as `let rate = 0.07` or `rate = 0.07`, or a function definition
+/// such as `f(x, y) = x^2 + y`.
+pub fn parse_statement(input: &str) -> Result<Statement, CalcError> {
+    let mut parser = Parser::new(input)?;
+    let statement = parser.parse_statement()?;
+    parser.expect_end()?;
+    Ok(statement)
+}
+
+/// The outcome of parsing input that may go on over several lines.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Parsed {
+    Complete(Statement),
+    /// Valid so far but cut short, like `(1 +`: more input could complete it.
+    /// The error is what parsing the input as it stands would report.
+    Incomplete(CalcError),
+    /// A syntax error that no further input can fix.
+    Invalid(CalcError),
+}
+
+/// Like [`parse_statement`], but tells input that stops too early apart from
+/// input that is wrong, so a REPL can keep reading lines:
+///
+/// ```
+/// use rust_calculator::{parse_partial, Parsed};
+///
+/// assert!(matches!(parse_partial("2 * (3 +"), Parsed::Incomplete(_)));
+/// assert!(matches!(parse_partial("2 * (3 + 4)"), Parsed::Complete(_)));
+/// assert!(matches!(parse_partial("2 * )"), Parsed::Invalid(_)));
+/// ```
+pub fn parse_partial(input: &str) -> Parsed {
+    match parse_statement(input) {
+        Ok(statement) => Parsed::Complete(statement),
+        Err(error) if error.is_incomplete() => Parsed::Incomplete(error),
+        Err(error) => Parsed::Invalid(error),
+    }
+}
+
+/// Recursive-descent parser using precedence climbing for the binary operators.
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Length of the input, used to point at the end of the line.
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Result<Parser<'a>, CalcError> {
+        Ok(Parser {
+            input,
+            tokens: tokenize(input)?,
+            pos: 0,
+            end: input.len(),
+        })
+    }
+
+    /// Fails if any tokens are left after a complete statement.
+    fn expect_end(&mut self) -> Result<(), CalcError> {
+        // Anything left over means two operands were written without an operator
+        match self.next() {
+            None => Ok(()),
+            Some(Token {
+                kind: TokenKind::RightParen,
+                span,
+            }) => Err(CalcError::UnmatchedParen { span }),
+            // A word like "x" between two numbers was most likely meant as an operator
+            Some(Token {
+                kind: TokenKind::Identifier(name),
+                span,
+            }) if is_operator_typo(&name) => Err(CalcError::UnknownOperator {
+                operator: name,
+                span,
+            }),
+            Some(token) => Err(unexpected(&token, "an operator")),
+        }
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&TokenKind> {
+        self.tokens.get(self.pos + offset).map(|token| &token.kind)
+    }
+
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|token| &token.kind)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consumes the next token if `predicate` accepts its kind.
+    fn next_if(&mut self, predicate: impl Fn(&TokenKind) -> bool) -> Option<Token> {
+        if self.peek().is_some_and(predicate) {
+            self.next()
+        } else {
+            None
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, CalcError> {
+        let is_let = matches!(self.peek(), Some(TokenKind::Identifier(word)) if word == "let");
+        let start = usize::from(is_let);
+        if self.is_definition(start) {
+            self.pos += start;
+            return self.parse_definition();
+        }
+        let is_assignment = matches!(
+            (self.peek(), self.peek_at(1)),
+            (Some(TokenKind::Identifier(_)), Some(TokenKind::Equals))
+        );
+        if !is_let && !is_assignment {
+            return Ok(Statement::Expr(self.parse_expression(0)?));
+        }
+
+        if is_let {
+            self.next();
+        }
+        let (name, name_span) = self.expect_identifier("a variable name")?;
+        self.expect_equals()?;
+        let value = self.parse_expression(0)?;
+        Ok(Statement::Assign {
+            name,
+            name_span,
+            value,
+        })
+    }
+
+    /// Whether the tokens from `offset` on read `name(a, b, ...) =`.
+    fn is_definition(&self, offset: usize) -> bool {
+        if !matches!(
+            (self.peek_at(offset), self.peek_at(offset + 1)),
+            (Some(TokenKind::Identifier(_)), Some(TokenKind::LeftParen))
+        ) {
+            return false;
+        }
+
+        let mut i = offset + 2;
+        if self.peek_at(i) != Some(&TokenKind::RightParen) {
+            loop {
+                if !matches!(self.peek_at(i), Some(TokenKind::Identifier(_))) {
+                    return false;
+                }
+                i += 1;
+                match self.peek_at(i) {
+                    Some(TokenKind::Comma) => i += 1,
+                    Some(TokenKind::RightParen) => break,
+                    _ => return false,
+                }
+            }
+        }
+        self.peek_at(i + 1) == Some(&TokenKind::Equals)
+    }
+
+    /// Parses `name(a, b, ...) = body`, already known to be well-formed up to the `=`.
+    fn parse_definition(&mut self) -> Result<Statement, CalcError> {
+        let (name, name_span) = self.expect_identifier("a function name")?;
+
+        // `is_definition` has checked the shape, so this only collects the
+        // names and skips the `(`, commas and `)`
+        let mut params = Vec::new();
+        self.next();
+        while let Some(token) = self.next() {
+            match token.kind {
+                TokenKind::Identifier(param) => params.push((param, token.span)),
+                TokenKind::RightParen => break,
+                _ => {}
+            }
+        }
+
+        self.expect_equals()?;
+        let body = self.parse_expression(0)?;
+        Ok(Statement::Define {
+            name,
+            name_span,
+            params,
+            body,
+        })
+    }
+
+    fn expect_identifier(&mut self, expected: &'static str) -> Result<(String, Span), CalcError> {
+        match self.next() {
+            Some(Token {
+                kind: TokenKind::Identifier(name),
+                span,
+            }) => Ok((name, span)),
+            Some(token) => Err(unexpected(&token, expected)),
+            None => Err(self.end_of_input()),
+        }
+    }
+
+    fn expect_equals(&mut self) -> Result<(), CalcError> {
+        match self.next() {
+            Some(Token {
+                kind: TokenKind::Equals,
+                ..
+            }) => Ok(()),
+            Some(token) => Err(unexpected(&token, "'='")),
+            None => Err(self.end_of_input()),
+        }
+    }
+
+    fn end_of_input(&self) -> CalcError {
+        CalcError::UnexpectedEnd {
+            span: Span::new(self.end, self.end),
+        }
+    }
+
+    /// Parses operators binding at least as tightly as `min_precedence`.
+    /// Most operators are left-associative, so `8 - 3 - 2` is `(8 - 3) - 2`,
+    /// but `2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`. A conversion with `to` or `in`
+    /// binds loosest, so it applies to everything before it.
+    fn parse_expression(&mut self, min_precedence: u8) -> Result<Expr, CalcError> {
+        let mut left = self.parse_operand()?;
+
+        loop {
+            if min_precedence == 0 && self.is_conversion() {
+                left = self.parse_conversion(left)?;
+                continue;
+            }
+            let Some(&TokenKind::Operator(symbol)) = self.peek() else {
+                break;
+            };
+            let Some(op) = BinaryOp::from_symbol(symbol) else {
+                break;
+            };
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.next();
+
+            // A right-associative operator lets its right operand continue
+            // with the same operator
+            let next_precedence = if op.is_right_associative() {
+                op.precedence()
+            } else {
+                op.precedence() + 1
+            };
+            let right = self.parse_expression(next_precedence)?;
+            let span = left.span().to(right.span());
+            left = Expr::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+                span,
+            };
+        }
+
+        Ok(left)
+    }
+
+    /// Parses a number, name, function call, parenthesised sub-expression or
+    /// any of those behind prefix `-` / `+` signs, followed by any number of
+    /// postfix `!` and then by an optional unit.
+    fn parse_operand(&mut self) -> Result<Expr, CalcError> {
+        let mut operand = self.parse_primary()?;
+        while let Some(token) = self.next_if(|kind| *kind == TokenKind::Operator("!")) {
+            operand = Expr::Unary {
+                op: UnaryOp::Factorial,
+                span: operand.span().to(token.span),
+                operand: Box::new(operand),
+            };
+        }
+        // Only a number takes a unit, so a name like `m` or `t` after a
+        // variable or a bracket is not read as one
+        let literal = matches!(operand, Expr::Number { .. } | Expr::Imaginary { .. });
+        if literal && self.is_unit_at(0) {
+            let (unit, unit_span) = self.parse_unit()?;
+            operand = Expr::Quantity {
+                span: operand.span().to(unit_span),
+                value: Box::new(operand),
+                unit,
+            };
+        }
+        Ok(operand)
+    }
+
+    /// Whether the token at `offset` is the name of a unit, and not of a
+    /// function being called like `min(1, 2)`.
+    fn is_unit_at(&self, offset: usize) -> bool {
+        matches!(self.peek_at(offset), Some(TokenKind::Identifier(name)) if units::is_unit(name))
+            && self.peek_at(offset + 1) != Some(&TokenKind::LeftParen)
+    }
+
+    /// Whether the token at the current position has no space before or
+    /// after it.
+    fn is_glued(&self) -> bool {
+        match self.tokens.get(self.pos.wrapping_sub(1)..=self.pos + 1) {
+            Some([before, token, after]) => {
+                before.span.end == token.span.start && token.span.end == after.span.start
+            }
+            _ => false,
+        }
+    }
+
+    fn is_conversion(&self) -> bool {
+        matches!(self.peek(), Some(TokenKind::Identifier(word)) if word == "to" || word == "in")
+    }
+
+    /// Parses `to unit` or `in unit` after the value to convert.
+    fn parse_conversion(&mut self, value: Expr) -> Result<Expr, CalcError> {
+        self.next();
+        let (unit, unit_span) = match self.peek() {
+            _ if self.is_unit_at(0) => self.parse_unit()?,
+            Some(TokenKind::Identifier(name)) => {
+                let names = units::UNITS.iter().map(|unit| unit.symbol);
+                return Err(CalcError::UnknownUnit {
+                    suggestion: closest_match(name, names).map(str::to_string),
+                    name: name.clone(),
+                    span: self.tokens[self.pos].span,
+                });
+            }
+            Some(_) => return Err(unexpected(&self.tokens[self.pos], "a unit")),
+            None => return Err(self.end_of_input()),
+        };
+        Ok(Expr::Convert {
+            span: value.span().to(unit_span),
+            value: Box::new(value),
+            unit,
+        })
+    }
+
+    /// Parses a unit such as `km`, `m/s^2` or `kW*h`, starting at the name of
+    /// a unit. Powers must be whole, and a `*` or `/` only continues the
+    /// unit if another unit follows, so `5 m * x` multiplies by `x`.
+    fn parse_unit(&mut self) -> Result<(Unit, Span), CalcError> {
+        let mut factors = Vec::new();
+        let mut span = self.tokens[self.pos].span;
+        let mut sign = 1;
+        loop {
+            let Some(Token {
+                kind: TokenKind::Identifier(name),
+                span: name_span,
+            }) = self.next()
+            else {
+                unreachable!("units start with a name");
+            };
+            span = span.to(name_span);
+            let mut power = sign;
+            if let Some((exponent, exponent_span)) = self.parse_unit_power() {
+                power *= exponent;
+                span = span.to(exponent_span);
+            }
+            factors.push((name, power));
+
+            // `m/s` is one unit, but `m / s` divides by a variable `s`
+            sign = match self.peek() {
+                Some(TokenKind::Operator("*")) if self.is_unit_at(1) && self.is_glued() => 1,
+                Some(TokenKind::Operator("/")) if self.is_unit_at(1) && self.is_glued() => -1,
+                _ => break,
+            };
+            self.next();
+        }
+        let unit = Unit::from_factors(&factors)
+            .expect("every name is a unit")
+            .check()
+            .map_err(|_| CalcError::Overflow { span })?;
+        Ok((unit, span))
+    }
+
+    /// Consumes a whole power like `^2` or `**-1` after the name of a unit.
+    fn parse_unit_power(&mut self) -> Option<(i32, Span)> {
+        if !matches!(self.peek(), Some(TokenKind::Operator("^" | "**"))) {
+            return None;
+        }
+        let negative = self.peek_at(1) == Some(&TokenKind::Operator("-"));
+        let offset = 1 + usize::from(negative);
+        let Some(&TokenKind::Number(value)) = self.peek_at(offset) else {
+            return None;
+        };
+        if value.fract() != 0.0 || value > f64::from(i8::MAX) {
+            return None;
+        }
+        let span = self.tokens[self.pos + offset].span;
+        self.pos += offset + 1;
+        let power = value as i32;
+        Some((if negative { -power } else { power }, span))
+    }
+
+    /// Parses an operand without its postfix operators.
+    fn parse_primary(&mut self) -> Result<Expr, CalcError> {
+        let Some(token) = self.next() else {
+            return Err(self.end_of_input());
+        };
+
+        match token.kind {
+            TokenKind::Number(value) => Ok(Expr::Number {
+                value,
+                text: self.input[token.span.start..token.span.end].to_string(),
+                span: token.span,
+            }),
+            TokenKind::Imaginary(value) => Ok(Expr::Imaginary {
+                value,
+                text: self.input[token.span.start..token.span.end].to_string(),
+                span: token.span,
+            }),
+            TokenKind::ResultRef(index) => Ok(Expr::ResultRef {
+                index,
+                span: token.span,
+            }),
+            // The operand of a sign may contain `^` but nothing looser, so
+            // `-2^2` is `-(2^2)` while `-2*3` is `(-2)*3`
+            TokenKind::Operator(sign @ ("-" | "+")) => {
+                let operand = self.parse_expression(UnaryOp::PRECEDENCE + 1)?;
+                Ok(Expr::Unary {
+                    op: if sign == "-" {
+                        UnaryOp::Neg
+                    } else {
+                        UnaryOp::Plus
+                    },
+                    span: token.span.to(operand.span()),
+                    operand: Box::new(operand),
+                })
+            }
+            TokenKind::Identifier(name) => {
+                if self.peek() == Some(&TokenKind::LeftParen) {
+                    return self.parse_call(name, token.span);
+                }
+                Ok(Expr::Variable {
+                    name,
+                    span: token.span,
+                })
+            }
+            TokenKind::LeftParen => {
+                let expr = self.parse_expression(0)?;
+                match self.next() {
+                    Some(Token {
+                        kind: TokenKind::RightParen,
+                        span,
+                    }) => Ok(expr.with_span(token.span.to(span))),
+                    None => Err(CalcError::UnclosedParen { span: token.span }),
+                    Some(next) => Err(unexpected(&next, "')'")),
+                }
+            }
+            _ => Err(unexpected(&token, "a number")),
+        }
+    }
+
+    /// Parses the argument list of `name(arg, ...)`; the name is already consumed.
+    fn parse_call(&mut self, name: String, name_span: Span) -> Result<Expr, CalcError> {
+        let Some(open) = self.next() else {
+            return Err(self.end_of_input());
+        };
+        let mut args = Vec::new();
+
+        if self.peek() != Some(&TokenKind::RightParen) {
+            loop {
+                args.push(self.parse_expression(0)?);
+                if self.peek() != Some(&TokenKind::Comma) {
+                    break;
+                }
+                self.next();
+            }
+        }
+
+        match self.next() {
+            Some(Token {
+                kind: TokenKind::RightParen,
+                span,
+            }) => Ok(Expr::Call {
+                name,
+                args,
+                span: name_span.to(span),
+            }),
+            None => Err(CalcError::UnclosedParen { span: open.span }),
+            Some(next) => Err(unexpected(&next, "',' or ')'")),
+        }
+    }
+}
+
+fn unexpected(token: &Token, expected: &'static str) -> CalcError {
+    CalcError::UnexpectedToken {
+        found: describe(&token.kind),
+        expected,
+        span: token.span,
+    }
+}
+
+fn describe(kind: &TokenKind) -> String {
+    match kind {
+        TokenKind::Number(number) => format!("'{}'", number),
+        TokenKind::Imaginary(number) => format!("'{}i'", number),
+        TokenKind::Identifier(name) => format!("'{}'", name),
+        TokenKind::Operator(operator) => format!("'{}'", operator),
+        TokenKind::LeftParen => "'('".to_string(),
+        TokenKind::RightParen => "')'".to_string(),
+        TokenKind::Comma => "','".to_string(),
+        TokenKind::Equals => "'='".to_string(),
+        TokenKind::ResultRef(index) => format!("'${}'", index),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_addition() {
+        assert_eq!(parse_and_calculate("5 + 3").unwrap(), 8.0);
+    }
+
+    #[test]
+    fn test_subtraction() {
+        assert_eq!(parse_and_calculate("10 - 4").unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_multiplication() {
+        assert_eq!(parse_and_calculate("3 * 7").unwrap(), 21.0);
+    }
+
+    #[test]
+    fn test_division() {
+        assert_eq!(parse_and_calculate("15 / 3").unwrap(), 5.0);
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        assert!(matches!(
+            parse_and_calculate("10 / 0"),
+            Err(CalcError::DivisionByZero { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_operator() {
+        assert_eq!(
+            parse_and_calculate("5 & 3"),
+            Err(CalcError::UnknownOperator {
+                operator: "&".to_string(),
+                span: Span::new(2, 3)
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_number() {
+        assert_eq!(
+            parse_and_calculate("1.2.3 + 3"),
+            Err(CalcError::ParseNumber {
+                text: "1.2.3".to_string(),
+                span: Span::new(0, 5)
+            })
+        );
+        assert!(matches!(
+            parse_and_calculate("abc + 3"),
+            Err(CalcError::UnknownVariable { .. })
+        ));
+    }
+
+    #[test]
+    fn test_wrong_format() {
+        assert!(matches!(
+            parse_and_calculate("5 +"),
+            Err(CalcError::UnexpectedEnd { .. })
+        ));
+        assert!(matches!(
+            parse_and_calculate("5 3"),
+            Err(CalcError::UnexpectedToken { .. })
+        ));
+        assert!(matches!(
+            parse_and_calculate(""),
+            Err(CalcError::UnexpectedEnd { .. })
+        ));
+    }
+
+    #[test]
+    fn test_long_expression() {
+        assert_eq!(parse_and_calculate("5 + 3 + 2").unwrap(), 10.0);
+        assert_eq!(parse_and_calculate("20 - 4 - 3 - 2").unwrap(), 11.0);
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(parse_and_calculate("2 + 3 * 4").unwrap(), 14.0);
+        assert_eq!(parse_and_calculate("10 - 6 / 2").unwrap(), 7.0);
+        assert_eq!(parse_and_calculate("2 * 3 + 4 * 5").unwrap(), 26.0);
+    }
+
+    #[test]
+    fn test_left_associativity() {
+        assert_eq!(parse_and_calculate("8 - 3 - 2").unwrap(), 3.0);
+        assert_eq!(parse_and_calculate("16 / 4 / 2").unwrap(), 2.0);
+    }
+
+    #[test]
+    fn test_parentheses() {
+        assert_eq!(parse_and_calculate("(1 + 2) / 3").unwrap(), 1.0);
+        assert_eq!(parse_and_calculate("2 * (3 + (4 - 1))").unwrap(), 12.0);
+        assert_eq!(parse_and_calculate("((7))").unwrap(), 7.0);
+    }
+
+    #[test]
+    fn test_unbalanced_parentheses() {
+        assert!(matches!(
+            parse_and_calculate("(1 + 2"),
+            Err(CalcError::UnclosedParen { .. })
+        ));
+        assert!(matches!(
+            parse_and_calculate("1 + 2)"),
+            Err(CalcError::UnmatchedParen { .. })
+        ));
+        assert!(matches!(
+            parse_and_calculate("()"),
+            Err(CalcError::UnexpectedToken { .. })
+        ));
+    }
+
+    #[test]
+    fn test_negative_numbers() {
+        assert_eq!(parse_and_calculate("-5 + 3").unwrap(), -2.0);
+        assert_eq!(parse_and_calculate("10 - -5").unwrap(), 15.0);
+        assert_eq!(parse_and_calculate("1e-3 * 1000").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_without_spaces() {
+        assert_eq!(parse_and_calculate("5+3").unwrap(), 8.0);
+        assert_eq!(parse_and_calculate("5 +3").unwrap(), 8.0);
+        assert_eq!(parse_and_calculate("(1+2)*3").unwrap(), 9.0);
+        assert_eq!(parse_and_calculate("10-3").unwrap(), 7.0);
+    }
+
+    #[test]
+    fn test_error_locations() {
+        assert_eq!(
+            parse_and_calculate("abc + 3").unwrap_err().span(),
+            Span::new(0, 3)
+        );
+        assert_eq!(
+            parse_and_calculate("10 / (5 - 5)").unwrap_err().span(),
+            Span::new(5, 12)
+        );
+        assert_eq!(
+            parse_and_calculate("(1 + 2").unwrap_err().span(),
+            Span::new(0, 1)
+        );
+        assert_eq!(
+            parse_and_calculate("5 +").unwrap_err().span(),
+            Span::new(3, 3)
+        );
+    }
+
+    #[test]
+    fn test_operator_suggestions() {
+        let error = parse_and_calculate("5 x 3").unwrap_err();
+        assert_eq!(error.code(), "E0002");
+        assert_eq!(error.help().as_deref(), Some("did you mean `*`?"));
+
+        let error = parse_and_calculate("8 ÷ 2").unwrap_err();
+        assert_eq!(error.help().as_deref(), Some("did you mean `/`?"));
+    }
+
+    #[test]
+    fn test_parse_builds_tree() {
+        let expr = parse("1 + 2 * 3").unwrap();
+        let Expr::Binary {
+            op,
+            left,
+            right,
+            span,
+        } = expr
+        else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(op, BinaryOp::Add);
+        assert_eq!(span, Span::new(0, 9));
+        assert!(matches!(*left, Expr::Number { value, .. } if value == 1.0));
+        assert!(matches!(
+            *right,
+            Expr::Binary {
+                op: BinaryOp::Mul,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_calls_and_variables() {
+        let expr = parse("max(x, 2)").unwrap();
+        let Expr::Call { name, args, span } = expr else {
+            panic!("expected a call");
+        };
+        assert_eq!(name, "max");
+        assert_eq!(span, Span::new(0, 9));
+        assert!(matches!(&args[0], Expr::Variable { name, .. } if name == "x"));
+        assert!(matches!(parse("f()"), Ok(Expr::Call { args, .. }) if args.is_empty()));
+        assert!(matches!(
+            parse("f(1,"),
+            Err(CalcError::UnexpectedEnd { .. })
+        ));
+        assert!(matches!(
+            parse("f(1 2)"),
+            Err(CalcError::UnexpectedToken { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unary_operators() {
+        assert_eq!(parse_and_calculate("-(3)").unwrap(), -3.0);
+        assert_eq!(parse_and_calculate("+5").unwrap(), 5.0);
+        assert_eq!(parse_and_calculate("--5").unwrap(), 5.0);
+        assert_eq!(parse_and_calculate("-(2 + 3) * 2").unwrap(), -10.0);
+        assert_eq!(parse_and_calculate("4 * -2").unwrap(), -8.0);
+        assert!(matches!(
+            parse_and_calculate("-"),
+            Err(CalcError::UnexpectedEnd { .. })
+        ));
+    }
+
+    #[test]
+    fn test_power() {
+        assert_eq!(parse_and_calculate("2 ^ 10").unwrap(), 1024.0);
+        assert_eq!(parse_and_calculate("2 ** 10").unwrap(), 1024.0);
+        assert_eq!(parse_and_calculate("2 * 3 ^ 2").unwrap(), 18.0);
+        assert_eq!(parse_and_calculate("(2 * 3) ^ 2").unwrap(), 36.0);
+    }
+
+    #[test]
+    fn test_power_is_right_associative() {
+        assert_eq!(parse_and_calculate("2 ^ 3 ^ 2").unwrap(), 512.0);
+        assert_eq!(parse_and_calculate("2 ** 3 ** 2").unwrap(), 512.0);
+        assert_eq!(parse_and_calculate("(2 ^ 3) ^ 2").unwrap(), 64.0);
+    }
+
+    #[test]
+    fn test_power_and_unary_minus() {
+        assert_eq!(parse_and_calculate("-2^2").unwrap(), -4.0);
+        assert_eq!(parse_and_calculate("(-2)^2").unwrap(), 4.0);
+        assert_eq!(parse_and_calculate("2^-1").unwrap(), 0.5);
+        assert_eq!(parse_and_calculate("-2^-2").unwrap(), -0.25);
+        assert_eq!(parse_and_calculate("-2 * 3").unwrap(), -6.0);
+    }
+
+    #[test]
+    fn test_power_errors() {
+        assert!(matches!(
+            parse_and_calculate("0 ^ -1"),
+            Err(CalcError::DivisionByZero { .. })
+        ));
+        assert!(matches!(
+            parse_and_calculate("10 ^ 400"),
+            Err(CalcError::Overflow { .. })
+        ));
+        assert!(matches!(
+            parse_and_calculate("2 ^"),
+            Err(CalcError::UnexpectedEnd { .. })
+        ));
+    }
+
+    #[test]
+    fn test_factorial() {
+        assert_eq!(parse_and_calculate("5!").unwrap(), 120.0);
+        assert_eq!(parse_and_calculate("3!!").unwrap(), 720.0);
+        assert_eq!(parse_and_calculate("2^3!").unwrap(), 64.0);
+        assert_eq!(parse_and_calculate("-3! + 10").unwrap(), 4.0);
+        assert_eq!(parse_and_calculate("(1 + 2)! * 0!").unwrap(), 6.0);
+        assert!(matches!(
+            parse_and_calculate("(-1)!"),
+            Err(CalcError::Domain { .. })
+        ));
+        assert!(matches!(
+            parse_and_calculate("2.5!"),
+            Err(CalcError::Domain { .. })
+        ));
+        assert!(matches!(
+            parse_and_calculate("!3"),
+            Err(CalcError::UnexpectedToken { .. })
+        ));
+        assert_eq!(parse("4!").unwrap().span(), Span::new(0, 2));
+    }
+
+    #[test]
+    fn test_remainder() {
+        assert_eq!(parse_and_calculate("5 % 3").unwrap(), 2.0);
+        assert_eq!(parse_and_calculate("-7 % 3").unwrap(), -1.0);
+        assert_eq!(parse_and_calculate("7 % -3").unwrap(), 1.0);
+        assert_eq!(parse_and_calculate("5.5 % 2").unwrap(), 1.5);
+    }
+
+    #[test]
+    fn test_euclidean_modulo() {
+        assert_eq!(parse_and_calculate("7 mod 3").unwrap(), 1.0);
+        assert_eq!(parse_and_calculate("-7 mod 3").unwrap(), 2.0);
+        assert_eq!(parse_and_calculate("7 mod -3").unwrap(), 1.0);
+        assert_eq!(parse_and_calculate("-7 mod -3").unwrap(), 2.0);
+    }
+
+    #[test]
+    fn test_floor_division() {
+        assert_eq!(parse_and_calculate("7 // 2").unwrap(), 3.0);
+        assert_eq!(parse_and_calculate("-7 // 2").unwrap(), -4.0);
+        assert_eq!(parse_and_calculate("7 // -2").unwrap(), -4.0);
+        assert_eq!(parse_and_calculate("7.5 // 2.5").unwrap(), 3.0);
+    }
+
+    #[test]
+    fn test_remainder_precedence() {
+        assert_eq!(parse_and_calculate("1 + 7 % 4").unwrap(), 4.0);
+        assert_eq!(parse_and_calculate("2 * 7 mod 4").unwrap(), 2.0);
+        assert_eq!(parse_and_calculate("2 ^ 3 // 3").unwrap(), 2.0);
+    }
+
+    #[test]
+    fn test_remainder_by_zero() {
+        for input in ["5 % 0", "5 mod 0", "5 // 0", "5 // (1 - 1)"] {
+            assert!(
+                matches!(
+                    parse_and_calculate(input),
+                    Err(CalcError::DivisionByZero { .. })
+                ),
+                "{} should be a division by zero",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_assignments() {
+        for input in ["let rate = 0.07", "rate = 0.07", "rate=0.07"] {
+            let Statement::Assign {
+                name,
+                name_span,
+                value,
+            } = parse_statement(input).unwrap()
+            else {
+                panic!("{} should be an assignment", input);
+            };
+            assert_eq!(name, "rate");
+            assert_eq!(&input[name_span.start..name_span.end], "rate");
+            assert_eq!(value.to_string(), "0.07");
+        }
+        assert!(matches!(
+            parse_statement("rate * 2"),
+            Ok(Statement::Expr(Expr::Binary { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_parse_assignment_errors() {
+        assert!(matches!(
+            parse_statement("let = 5"),
+            Err(CalcError::UnexpectedToken { .. })
+        ));
+        assert!(matches!(
+            parse_statement("let x 5"),
+            Err(CalcError::UnexpectedToken { .. })
+        ));
+        assert!(matches!(
+            parse_statement("x ="),
+            Err(CalcError::UnexpectedEnd { .. })
+        ));
+        assert!(matches!(
+            parse_statement("2 = x"),
+            Err(CalcError::UnexpectedToken { .. })
+        ));
+        // Assignments are statements, not expressions
+        assert!(matches!(
+            parse("x = 1"),
+            Err(CalcError::UnexpectedToken { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_definitions() {
+        let Statement::Define {
+            name, params, body, ..
+        } = parse_statement("f(x, y) = x^2 + y").unwrap()
+        else {
+            panic!("expected a definition");
+        };
+        assert_eq!(name, "f");
+        let names: Vec<&str> = params.iter().map(|(param, _)| param.as_str()).collect();
+        assert_eq!(names, vec!["x", "y"]);
+        assert_eq!(params[1].1, Span::new(5, 6));
+        assert_eq!(body.to_string(), "x ^ 2 + y");
+
+        assert!(matches!(
+            parse_statement("let two() = 2"),
+            Ok(Statement::Define { params, .. }) if params.is_empty()
+        ));
+        // Calls with non-name arguments are still expressions
+        assert!(matches!(parse_statement("f(2, y)"), Ok(Statement::Expr(_))));
+        assert!(matches!(
+            parse_statement("f(2) = 3"),
+            Err(CalcError::UnexpectedToken { .. })
+        ));
+        assert!(matches!(
+            parse_statement("f(x) ="),
+            Err(CalcError::UnexpectedEnd { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        for input in ["(1 + 2", "5 +", "max(1,", "sqrt(", "-", "x =", "let", "f(x) =", "2 ^ (3 *"] {
+            assert!(matches!(parse_partial(input), Parsed::Incomplete(_)), "{}", input);
+        }
+        for input in ["1 + 2)", "5 + * 2", "2 3", "(1 + 2abc", "f(2) = 3", "5 $ 3"] {
+            assert!(matches!(parse_partial(input), Parsed::Invalid(_)), "{}", input);
+        }
+        // Lines are joined before parsing again
+        assert!(matches!(parse_partial("(1 + 2 sqrt(4))"), Parsed::Invalid(_)));
+        assert!(matches!(parse_partial("(1 + sqrt(4))"), Parsed::Complete(_)));
+    }
+
+    #[test]
+    fn test_parse_units() {
+        assert!(matches!(parse("5 km"), Ok(Expr::Quantity { .. })));
+        assert!(matches!(parse("5 km to m"), Ok(Expr::Convert { .. })));
+        // A `*` or `/` only continues a unit when another unit follows
+        // without spaces
+        assert!(matches!(
+            parse("5 m * s"),
+            Ok(Expr::Binary {
+                op: BinaryOp::Mul,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("5 m * x"),
+            Ok(Expr::Binary {
+                op: BinaryOp::Mul,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("5 m * min(1, 2)"),
+            Ok(Expr::Binary {
+                op: BinaryOp::Mul,
+                ..
+            })
+        ));
+        assert_eq!(parse("1+2 km in m").unwrap().to_string(), "1 + 2 km to m");
+        assert_eq!(
+            parse("3 kg*m**2/s^-1 to J*s").unwrap().to_string(),
+            "3 kg*m^2*s to J*s"
+        );
+        // Plain numbers have no unit, so quantities come out in SI base units
+        assert_eq!(parse_and_calculate("5km + 300m").unwrap(), 5300.0);
+        assert_eq!(parse_and_calculate("1 h").unwrap(), 3600.0);
+        // unless they are converted to a unit of their own
+        let speed = parse_and_calculate("60 mph to km/h").unwrap();
+        assert!((speed - 96.56064).abs() < 1e-9, "{}", speed);
+        assert_eq!(parse_and_calculate("(1 h to min)").unwrap(), 60.0);
+        assert!(matches!(
+            parse("5 m to meters"),
+            Err(CalcError::UnknownUnit { .. })
+        ));
+        assert!(matches!(
+            parse("5 m to 3"),
+            Err(CalcError::UnexpectedToken { .. })
+        ));
+        assert!(matches!(parse_partial("5 km in"), Parsed::Incomplete(_)));
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert!(matches!(
+            parse_and_calculate("1e308 * 10"),
+            Err(CalcError::Overflow { .. })
+        ));
+        assert_eq!(parse_and_calculate("inf + 1").unwrap(), f64::INFINITY);
+    }
+}
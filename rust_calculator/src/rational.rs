@@ -0,0 +1,277 @@
+//! Exact fractions for rational mode.
+//!
+//! Numerators and denominators are integers of any size, and every value is
+//! kept in lowest terms with a positive denominator, so `2/4` is `1/2`.
+//! Parsing, `+ - * / // % mod`, integer powers, roots that come out exact
+//! and the functions in [`call`] never round. Anything whose result is not
+//! rational, such as `sqrt(2)`, `ln` or `pi`, is computed as `f64`, and
+//! calculations involving that float stay floats.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+use crate::decimal::{self, Settings};
+
+/// Powers whose result would need more bits than this go through `f64`,
+/// which reports them as an overflow instead of building a number with
+/// hundreds of thousands of digits.
+const MAX_EXACT_BITS: u64 = 1 << 20;
+
+/// Decimals whose exponent is larger than this, like `1e100000`, go through
+/// `f64` too. Every sum has to reduce its fraction to lowest terms, which
+/// takes seconds once the power of ten has a hundred thousand digits.
+const MAX_EXACT_DIGITS: u64 = 10_000;
+
+/// How fractions are printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FractionStyle {
+    /// `3/2`.
+    #[default]
+    Improper,
+    /// `1 1/2`, a whole number and a proper fraction.
+    Mixed,
+    /// `1.5`, rounded with the decimal mode's precision and rounding.
+    Decimal,
+}
+
+impl FractionStyle {
+    /// Every style's name, as `--fractions` and `:fractions` accept them.
+    pub const NAMES: &'static [&'static str] = &["improper", "mixed", "decimal"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FractionStyle::Improper => "improper",
+            FractionStyle::Mixed => "mixed",
+            FractionStyle::Decimal => "decimal",
+        }
+    }
+}
+
+impl fmt::Display for FractionStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for FractionStyle {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<FractionStyle, String> {
+        match name.to_lowercase().as_str() {
+            "improper" => Ok(FractionStyle::Improper),
+            "mixed" => Ok(FractionStyle::Mixed),
+            "decimal" => Ok(FractionStyle::Decimal),
+            _ => Err(format!(
+                "unknown fraction style '{}', expected one of {}",
+                name,
+                FractionStyle::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Reads a number literal such as `1.5e-3` exactly, as `3/2000`.
+pub fn parse(text: &str) -> Option<BigRational> {
+    from_decimal(&decimal::parse(text)?)
+}
+
+/// The fraction a decimal stands for, such as `1/8` for `0.125`, or `None`
+/// if its exponent is too large to write out, like that of `1e-999999999`.
+pub fn from_decimal(value: &BigDecimal) -> Option<BigRational> {
+    let (digits, scale) = value.as_bigint_and_scale();
+    if scale.unsigned_abs() > MAX_EXACT_DIGITS {
+        return None;
+    }
+    let power = BigInt::from(10).pow(scale.unsigned_abs() as u32);
+    Some(if scale >= 0 {
+        BigRational::new(digits.into_owned(), power)
+    } else {
+        BigRational::from_integer(digits.into_owned() * power)
+    })
+}
+
+/// `value` rounded to the decimal mode's precision and rounding.
+pub fn to_decimal(value: &BigRational, settings: &Settings) -> BigDecimal {
+    let numer = BigDecimal::from(value.numer().clone());
+    let denom = BigDecimal::from(value.denom().clone());
+    decimal::divide(&numer, &denom, settings)
+}
+
+/// The nearest `f64` to `value`.
+pub fn to_f64(value: &BigRational) -> f64 {
+    value.to_f64().unwrap_or(f64::NAN)
+}
+
+/// Prints `value` in `style`. Whole numbers print without a denominator
+/// in every style.
+pub fn format(value: &BigRational, style: FractionStyle, settings: &Settings) -> String {
+    if value.is_integer() {
+        return value.numer().to_string();
+    }
+    match style {
+        FractionStyle::Improper => format!("{}/{}", value.numer(), value.denom()),
+        FractionStyle::Mixed => {
+            let whole = value.trunc();
+            if whole.is_zero() {
+                return format!("{}/{}", value.numer(), value.denom());
+            }
+            let part = (value - &whole).abs();
+            format!("{} {}/{}", whole.numer(), part.numer(), part.denom())
+        }
+        FractionStyle::Decimal => decimal::to_plain_string(&to_decimal(value, settings)),
+    }
+}
+
+/// `a // b`, rounded towards negative infinity. `b` must not be zero.
+pub fn floor_divide(a: &BigRational, b: &BigRational) -> BigRational {
+    (a / b).floor()
+}
+
+/// `a % b`, which takes the sign of `a`. `b` must not be zero.
+pub fn remainder(a: &BigRational, b: &BigRational) -> BigRational {
+    a - b * (a / b).trunc()
+}
+
+/// `a mod b`, always in `0..|b|`. `b` must not be zero.
+pub fn modulo(a: &BigRational, b: &BigRational) -> BigRational {
+    let result = remainder(a, b);
+    if result.is_negative() {
+        result + b.abs()
+    } else {
+        result
+    }
+}
+
+/// `base ^ exponent` when the result is rational and of a sensible size,
+/// such as `(4/9) ^ (3/2)`, which is `8/27`, or `None` when the power has
+/// to be computed as `f64`. A zero base with a negative exponent must
+/// already have been ruled out.
+pub fn pow(base: &BigRational, exponent: &BigRational) -> Option<BigRational> {
+    let base = match exponent.denom().to_u32()? {
+        1 => base.clone(),
+        degree => root(base, degree)?,
+    };
+    let power = exponent.numer().to_i32()?;
+    let bits = (base.numer().bits() + base.denom().bits()) * u64::from(power.unsigned_abs());
+    if bits > MAX_EXACT_BITS {
+        return None;
+    }
+    Some(base.pow(power))
+}
+
+/// The `degree`th root of `value` if it is rational. Odd roots of negative
+/// numbers are negative; even roots of them have no rational value.
+fn root(value: &BigRational, degree: u32) -> Option<BigRational> {
+    if value.is_negative() {
+        return if degree % 2 == 1 {
+            root(&-value, degree).map(|root| -root)
+        } else {
+            None
+        };
+    }
+    let exact = |n: &BigInt| {
+        let root = n.nth_root(degree);
+        (root.pow(degree) == *n).then_some(root)
+    };
+    Some(BigRational::new(
+        exact(value.numer())?,
+        exact(value.denom())?,
+    ))
+}
+
+/// The exact version of the built-in function `name`, or `None` if it has
+/// none or its result is not rational for these arguments, in which case
+/// the `f64` version is used. Arguments outside a function's domain always
+/// return `None`, so the error comes from the `f64` version too.
+pub fn call(name: &str, args: &[BigRational]) -> Option<BigRational> {
+    let result = match name {
+        "sqrt" if !args[0].is_negative() => root(&args[0], 2)?,
+        "cbrt" => root(&args[0], 3)?,
+        "abs" => args[0].abs(),
+        "floor" => args[0].floor(),
+        "ceil" => args[0].ceil(),
+        // Halves go away from zero, like `f64::round`
+        "round" => args[0].round(),
+        "trunc" => args[0].trunc(),
+        "min" => args.iter().min()?.clone(),
+        "max" => args.iter().max()?.clone(),
+        "hypot" => root(&(&args[0] * &args[0] + &args[1] * &args[1]), 2)?,
+        _ => return None,
+    };
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn r(numer: i64, denom: i64) -> BigRational {
+        BigRational::new(numer.into(), denom.into())
+    }
+
+    #[test]
+    fn test_parse_and_format() {
+        assert_eq!(parse("0.125"), Some(r(1, 8)));
+        assert_eq!(parse("1.5e-3"), Some(r(3, 2000)));
+        assert_eq!(parse("2e3"), Some(r(2000, 1)));
+        assert_eq!(parse("1e-999999999"), None);
+        assert!(parse("1e10000").is_some());
+        assert_eq!(parse("1e10001"), None);
+        assert_eq!(parse("1e100000"), None);
+        let default = Settings::default();
+        let show = |value, style| format(&value, style, &default);
+        assert_eq!(show(r(3, 2), FractionStyle::Improper), "3/2");
+        assert_eq!(show(r(-3, 2), FractionStyle::Mixed), "-1 1/2");
+        assert_eq!(show(r(-1, 2), FractionStyle::Mixed), "-1/2");
+        assert_eq!(show(r(4, 2), FractionStyle::Mixed), "2");
+        assert_eq!(
+            show(r(1, 3), FractionStyle::Decimal),
+            "0.3333333333333333333333333333"
+        );
+        for name in FractionStyle::NAMES {
+            assert_eq!(name.parse::<FractionStyle>().unwrap().name(), *name);
+        }
+        assert!("proper".parse::<FractionStyle>().is_err());
+    }
+
+    #[test]
+    fn test_remainders() {
+        assert_eq!(floor_divide(&r(-7, 2), &r(1, 1)), r(-4, 1));
+        assert_eq!(remainder(&r(-7, 1), &r(3, 1)), r(-1, 1));
+        assert_eq!(remainder(&r(7, 2), &r(1, 3)), r(1, 6));
+        assert_eq!(modulo(&r(-7, 1), &r(3, 1)), r(2, 1));
+        assert_eq!(modulo(&r(-1, 2), &r(-1, 3)), r(1, 6));
+    }
+
+    #[test]
+    fn test_powers_and_functions() {
+        assert_eq!(pow(&r(2, 3), &r(-2, 1)), Some(r(9, 4)));
+        assert_eq!(pow(&r(4, 9), &r(3, 2)), Some(r(8, 27)));
+        assert_eq!(pow(&r(-8, 1), &r(1, 3)), Some(r(-2, 1)));
+        assert_eq!(pow(&r(2, 1), &r(1, 2)), None);
+        assert_eq!(pow(&r(-4, 1), &r(1, 2)), None);
+        assert_eq!(pow(&r(2, 1), &r(10_000_000, 1)), None);
+        assert_eq!(call("sqrt", &[r(9, 16)]), Some(r(3, 4)));
+        assert_eq!(call("sqrt", &[r(2, 1)]), None);
+        assert_eq!(call("sqrt", &[r(-4, 1)]), None);
+        assert_eq!(call("round", &[r(-5, 2)]), Some(r(-3, 1)));
+        assert_eq!(call("max", &[r(1, 3), r(1, 2)]), Some(r(1, 2)));
+        assert_eq!(call("hypot", &[r(3, 5), r(4, 5)]), Some(r(1, 1)));
+        assert_eq!(call("sin", &[r(1, 1)]), None);
+    }
+
+    #[test]
+    fn test_conversions() {
+        let default = Settings::default();
+        assert_eq!(
+            to_decimal(&r(1, 8), &default),
+            decimal::parse("0.125").unwrap()
+        );
+        assert_eq!(to_f64(&r(1, 4)), 0.25);
+    }
+}
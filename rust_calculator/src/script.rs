@@ -0,0 +1,151 @@
+use crate::lexer::Span;
+
+/// The text of one statement in a script, and where it starts in the file.
+///
+/// Comments and line continuations are blanked out with spaces rather than
+/// removed, so a byte offset into `text` is also a byte offset into the
+/// file once `offset` is added.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub text: String,
+    pub offset: usize,
+    /// The line `text` starts on, counting from 1.
+    pub line: usize,
+}
+
+impl Chunk {
+    /// Turns a span within this statement into a span within the file.
+    pub fn locate(&self, span: Span) -> Span {
+        Span::new(span.start + self.offset, span.end + self.offset)
+    }
+}
+
+/// Splits a script into statements. Statements end at a newline or a `;`,
+/// `#` starts a comment that runs to the end of the line, and a line ending
+/// in `\` continues on the next one. Blank statements are skipped.
+///
+/// ```
+/// let chunks = rust_calculator::script::split("x = 1; y = 2  # setup\nx + \\\n  y\n");
+/// let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+/// assert_eq!(texts, vec!["x = 1", "y = 2", "x +     y"]);
+/// ```
+pub fn split(source: &str) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut text = String::new();
+    let mut offset = 0;
+    let mut i = 0;
+    while let Some(c) = source[i..].chars().next() {
+        let rest_of_line = source[i + c.len_utf8()..].split('\n').next().unwrap_or("");
+        match c {
+            '#' => {
+                let comment = c.len_utf8() + rest_of_line.len();
+                text.push_str(&" ".repeat(comment));
+                i += comment;
+            }
+            '\\' if is_continuation(&source[i..], rest_of_line) => {
+                // The backslash, anything after it and the newline itself
+                let continuation = 1 + rest_of_line.len() + 1;
+                text.push_str(&" ".repeat(continuation));
+                i += continuation;
+            }
+            ';' | '\n' => {
+                finish(&mut chunks, &mut text, offset);
+                i += 1;
+                offset = i;
+            }
+            _ => {
+                text.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+    finish(&mut chunks, &mut text, offset);
+    // Count lines once over the whole file rather than once per statement
+    let mut line = 1;
+    let mut counted = 0;
+    for chunk in &mut chunks {
+        line += source[counted..chunk.offset].matches('\n').count();
+        counted = chunk.offset;
+        chunk.line = line;
+    }
+    chunks
+}
+
+/// A `\` continues the line when only whitespace or a comment follows it
+/// and there is a next line to continue on.
+fn is_continuation(from_backslash: &str, rest_of_line: &str) -> bool {
+    let code = rest_of_line.split('#').next().unwrap_or("");
+    code.trim().is_empty() && from_backslash.len() > 1 + rest_of_line.len()
+}
+
+/// Ends the current statement, trimming the surrounding whitespace so that
+/// `text` starts exactly at `offset`.
+fn finish(chunks: &mut Vec<Chunk>, text: &mut String, offset: usize) {
+    let text = std::mem::take(text);
+    let trimmed = text.trim();
+    if !trimmed.is_empty() {
+        chunks.push(Chunk {
+            text: trimmed.to_string(),
+            offset: offset + (text.len() - text.trim_start().len()),
+            line: 0,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(source: &str) -> Vec<String> {
+        split(source).into_iter().map(|chunk| chunk.text).collect()
+    }
+
+    #[test]
+    fn test_lines_and_semicolons() {
+        assert_eq!(
+            texts("a = 1\nb = 2; a + b;\n"),
+            vec!["a = 1", "b = 2", "a + b"]
+        );
+        assert_eq!(texts("1\r\n2\r\n"), vec!["1", "2"]);
+    }
+
+    #[test]
+    fn test_comments_and_blank_lines() {
+        let source = "# Monthly budget\n\nrent = 1200  # due on the 1st\n   \n;;\n#\nrent * 12";
+        assert_eq!(texts(source), vec!["rent = 1200", "rent * 12"]);
+    }
+
+    #[test]
+    fn test_line_continuations() {
+        let source = "total = 1 + \\\n    2 + \\  # still going\n    3\ntotal";
+        let chunks = split(source);
+        assert_eq!(chunks.len(), 2);
+        assert!(!chunks[0].text.contains('\n'));
+        let statement = crate::parse_statement(&chunks[0].text).unwrap();
+        assert_eq!(statement.to_string(), "total = 1 + 2 + 3");
+        // Only a trailing backslash continues a line
+        assert_eq!(texts("1 \\ 2\n3 \\"), vec!["1 \\ 2", "3 \\"]);
+    }
+
+    #[test]
+    fn test_offsets_point_into_the_source() {
+        let source = "x = 1 # é\ny = x \\\n  / 0";
+        let chunks = split(source);
+        assert_eq!(chunks[1].offset, source.find('y').unwrap());
+        assert_eq!(split(" ; x")[0].offset, 3);
+        let lines: Vec<usize> = chunks.iter().map(|chunk| chunk.line).collect();
+        assert_eq!(lines, vec![1, 2]);
+        let lines: Vec<usize> = split("a\n\nb; c\\\n d\ne")
+            .iter()
+            .map(|chunk| chunk.line)
+            .collect();
+        assert_eq!(lines, vec![1, 3, 3, 5]);
+        for chunk in &chunks {
+            let span = chunk.locate(Span::new(0, 1));
+            assert_eq!(&source[span.start..span.end], &chunk.text[..1]);
+        }
+        let slash = chunks[1].text.find('/').unwrap();
+        let span = chunks[1].locate(Span::new(slash, slash + 1));
+        assert_eq!(span.start, source.find('/').unwrap());
+    }
+}
@@ -0,0 +1,563 @@
+//! Physical units, written after a number like `5 km` or `9.81 m/s^2`.
+//!
+//! A name is only a unit right after a number or after `to` and `in`, and
+//! units only combine without spaces, as in `m/s`. Anywhere else a name is
+//! a variable, so `m` and `t` can still be used as names: `5 m * t` is
+//! five metres times `t`, and `x * 1 m` gives a variable a unit.
+//!
+//! A quantity keeps its number in the unit it was written in, and is only
+//! converted when it meets another unit of the same dimension, so
+//! `5 km + 300 m` is `5.3 km`. Quantities of different dimensions can be
+//! multiplied and divided, like `3 kW * 2 h`, but adding them or converting
+//! between them, like `5 m + 3 s`, is an error. Every unit with a prefix
+//! mark below also takes the SI prefixes, so `km`, `mg` and `kWh` work.
+//! Values are `f64` in every mode.
+
+use std::fmt;
+
+/// The SI base units, in the order of a [`Dimension`]'s exponents.
+const BASE_UNITS: [&str; 7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+/// What a unit measures, as the powers of the SI base units it is made of:
+/// a speed is length^1 time^-1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Dimension([i8; 7]);
+
+impl Dimension {
+    /// A number without a dimension, such as a ratio or an angle.
+    pub const NONE: Dimension = Dimension([0; 7]);
+
+    const fn new(m: i8, kg: i8, s: i8, a: i8, k: i8, mol: i8, cd: i8) -> Dimension {
+        Dimension([m, kg, s, a, k, mol, cd])
+    }
+
+    pub fn is_none(self) -> bool {
+        self == Dimension::NONE
+    }
+
+    /// This dimension times `other` raised to `power`.
+    fn times(self, other: Dimension, power: i32) -> Result<Dimension, PowerOverflow> {
+        let mut result = self;
+        for (exponent, other) in result.0.iter_mut().zip(other.0) {
+            *exponent = i32::from(other)
+                .checked_mul(power)
+                .and_then(|power| power.checked_add(i32::from(*exponent)))
+                .and_then(|power| i8::try_from(power).ok())
+                .ok_or(PowerOverflow)?;
+        }
+        Ok(result)
+    }
+}
+
+/// The error for a unit with a power beyond what units can have, like
+/// `m^200`. Each named unit and each base unit it is made of can be raised
+/// to at most the 127th power, either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerOverflow;
+
+impl fmt::Display for Dimension {
+    /// Prints the dimension in SI base units, such as `kg*m^2/s^3`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let factors: Vec<(String, i32)> = BASE_UNITS
+            .iter()
+            .zip(self.0)
+            .filter(|(_, power)| *power != 0)
+            .map(|(unit, power)| (unit.to_string(), i32::from(power)))
+            .collect();
+        write_factors(f, &factors)
+    }
+}
+
+const LENGTH: Dimension = Dimension::new(1, 0, 0, 0, 0, 0, 0);
+const AREA: Dimension = Dimension::new(2, 0, 0, 0, 0, 0, 0);
+const VOLUME: Dimension = Dimension::new(3, 0, 0, 0, 0, 0, 0);
+const MASS: Dimension = Dimension::new(0, 1, 0, 0, 0, 0, 0);
+const TIME: Dimension = Dimension::new(0, 0, 1, 0, 0, 0, 0);
+const SPEED: Dimension = Dimension::new(1, 0, -1, 0, 0, 0, 0);
+const ENERGY: Dimension = Dimension::new(2, 1, -2, 0, 0, 0, 0);
+const POWER: Dimension = Dimension::new(2, 1, -3, 0, 0, 0, 0);
+const PRESSURE: Dimension = Dimension::new(-1, 1, -2, 0, 0, 0, 0);
+
+/// A named unit such as `m` or `mph`.
+#[derive(Debug)]
+pub struct UnitDef {
+    pub symbol: &'static str,
+    pub description: &'static str,
+    /// The size of one of this unit in SI base units.
+    pub scale: f64,
+    pub dimension: Dimension,
+    /// Whether SI prefixes can be put in front, as in `km`.
+    pub prefixes: bool,
+}
+
+const fn si(
+    symbol: &'static str,
+    description: &'static str,
+    scale: f64,
+    dimension: Dimension,
+) -> UnitDef {
+    UnitDef {
+        symbol,
+        description,
+        scale,
+        dimension,
+        prefixes: true,
+    }
+}
+
+const fn other(
+    symbol: &'static str,
+    description: &'static str,
+    scale: f64,
+    dimension: Dimension,
+) -> UnitDef {
+    UnitDef {
+        symbol,
+        description,
+        scale,
+        dimension,
+        prefixes: false,
+    }
+}
+
+/// Every unit, in the order `:units` lists them. Inches are `inch`, since
+/// `in` converts between units.
+pub const UNITS: &[UnitDef] = &[
+    si("m", "metre", 1.0, LENGTH),
+    si("g", "gram", 1e-3, MASS),
+    si("s", "second", 1.0, TIME),
+    si("A", "ampere", 1.0, Dimension::new(0, 0, 0, 1, 0, 0, 0)),
+    si("K", "kelvin", 1.0, Dimension::new(0, 0, 0, 0, 1, 0, 0)),
+    si("mol", "mole", 1.0, Dimension::new(0, 0, 0, 0, 0, 1, 0)),
+    si("cd", "candela", 1.0, Dimension::new(0, 0, 0, 0, 0, 0, 1)),
+    si("Hz", "hertz", 1.0, Dimension::new(0, 0, -1, 0, 0, 0, 0)),
+    si("N", "newton", 1.0, Dimension::new(1, 1, -2, 0, 0, 0, 0)),
+    si("Pa", "pascal", 1.0, PRESSURE),
+    si("J", "joule", 1.0, ENERGY),
+    si("W", "watt", 1.0, POWER),
+    si("C", "coulomb", 1.0, Dimension::new(0, 0, 1, 1, 0, 0, 0)),
+    si("V", "volt", 1.0, Dimension::new(2, 1, -3, -1, 0, 0, 0)),
+    si("ohm", "ohm", 1.0, Dimension::new(2, 1, -3, -2, 0, 0, 0)),
+    si("F", "farad", 1.0, Dimension::new(-2, -1, 4, 2, 0, 0, 0)),
+    si("T", "tesla", 1.0, Dimension::new(0, 1, -2, -1, 0, 0, 0)),
+    si("Wb", "weber", 1.0, Dimension::new(2, 1, -2, -1, 0, 0, 0)),
+    si("H", "henry", 1.0, Dimension::new(2, 1, -2, -2, 0, 0, 0)),
+    si("L", "litre", 1e-3, VOLUME),
+    si("t", "tonne", 1e3, MASS),
+    si("Wh", "watt-hour", 3600.0, ENERGY),
+    si("eV", "electronvolt", 1.602_176_634e-19, ENERGY),
+    si("cal", "calorie", 4.184, ENERGY),
+    si("bar", "bar", 1e5, PRESSURE),
+    si("rad", "radian", 1.0, Dimension::NONE),
+    other(
+        "deg",
+        "degree of arc",
+        std::f64::consts::PI / 180.0,
+        Dimension::NONE,
+    ),
+    other("min", "minute", 60.0, TIME),
+    other("h", "hour", 3600.0, TIME),
+    other("day", "day", 86_400.0, TIME),
+    other("week", "week", 604_800.0, TIME),
+    other("year", "Julian year of 365.25 days", 31_557_600.0, TIME),
+    other("inch", "inch", 0.0254, LENGTH),
+    other("ft", "foot", 0.3048, LENGTH),
+    other("yd", "yard", 0.9144, LENGTH),
+    other("mi", "mile", 1609.344, LENGTH),
+    other("nmi", "nautical mile", 1852.0, LENGTH),
+    other("ha", "hectare", 1e4, AREA),
+    other("acre", "acre", 4_046.856_422_4, AREA),
+    other("gal", "US gallon", 3.785_411_784e-3, VOLUME),
+    other("lb", "pound", 0.453_592_37, MASS),
+    other("oz", "ounce", 0.028_349_523_125, MASS),
+    other("mph", "mile per hour", 0.447_04, SPEED),
+    other(
+        "kn",
+        "knot, a nautical mile per hour",
+        1852.0 / 3600.0,
+        SPEED,
+    ),
+    other("atm", "standard atmosphere", 101_325.0, PRESSURE),
+    other(
+        "psi",
+        "pound-force per square inch",
+        6_894.757_293_168_361,
+        PRESSURE,
+    ),
+    other("hp", "mechanical horsepower", 745.699_871_582_270_2, POWER),
+];
+
+/// The SI prefixes, two-letter `da` before the one-letter ones. `u` is an
+/// ASCII spelling of `µ`.
+pub const PREFIXES: &[(&str, f64)] = &[
+    ("da", 1e1),
+    ("Y", 1e24),
+    ("Z", 1e21),
+    ("E", 1e18),
+    ("P", 1e15),
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("u", 1e-6),
+    ("µ", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+    ("f", 1e-15),
+    ("a", 1e-18),
+    ("z", 1e-21),
+    ("y", 1e-24),
+];
+
+/// Whether `name` is a unit, with or without a prefix.
+pub fn is_unit(name: &str) -> bool {
+    Unit::lookup(name).is_some()
+}
+
+/// One named unit raised to a power, like the `s^-2` of `m/s^2`.
+#[derive(Debug, Clone, PartialEq)]
+struct Factor {
+    symbol: String,
+    /// The size of the unit, without the power, in SI base units.
+    scale: f64,
+    dimension: Dimension,
+    power: i32,
+}
+
+/// The unit of a quantity: a product of named units raised to whole
+/// powers, or no unit at all.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Unit {
+    factors: Vec<Factor>,
+}
+
+impl Unit {
+    /// The unit called `symbol`, such as `mph` or `km`. A name that is a
+    /// unit of its own, like `min`, is never read as a prefix and a unit.
+    pub fn lookup(symbol: &str) -> Option<Unit> {
+        let (scale, unit) = match UNITS.iter().find(|unit| unit.symbol == symbol) {
+            Some(unit) => (1.0, unit),
+            None => PREFIXES.iter().find_map(|(prefix, scale)| {
+                let rest = symbol.strip_prefix(prefix)?;
+                let unit = UNITS
+                    .iter()
+                    .find(|unit| unit.prefixes && unit.symbol == rest)?;
+                Some((*scale, unit))
+            })?,
+        };
+        Some(Unit {
+            factors: vec![Factor {
+                symbol: symbol.to_string(),
+                scale: scale * unit.scale,
+                dimension: unit.dimension,
+                power: 1,
+            }],
+        })
+    }
+
+    /// The product of the named units raised to their powers, as written
+    /// in `m/s^2`, or `None` if one of the names is not a unit. Only the
+    /// same name is merged, so `km*m` keeps both.
+    pub fn from_factors(factors: &[(String, i32)]) -> Option<Unit> {
+        let mut result = Unit::default();
+        for (symbol, power) in factors {
+            let mut unit = Unit::lookup(symbol)?;
+            match result
+                .factors
+                .iter_mut()
+                .find(|factor| factor.symbol == *symbol)
+            {
+                Some(factor) => factor.power += power,
+                None => {
+                    unit.factors[0].power = *power;
+                    result.factors.append(&mut unit.factors);
+                }
+            }
+        }
+        result.factors.retain(|factor| factor.power != 0);
+        Some(result)
+    }
+
+    /// Whether this is no unit at all, as for a plain number.
+    pub fn is_none(&self) -> bool {
+        self.factors.is_empty()
+    }
+
+    pub fn dimension(&self) -> Result<Dimension, PowerOverflow> {
+        self.factors
+            .iter()
+            .try_fold(Dimension::NONE, |dimension, factor| {
+                dimension.times(factor.dimension, factor.power)
+            })
+    }
+
+    /// This unit, or an error if one of its powers is too large.
+    pub fn check(self) -> Result<Unit, PowerOverflow> {
+        if self
+            .factors
+            .iter()
+            .any(|factor| i8::try_from(factor.power).is_err())
+        {
+            return Err(PowerOverflow);
+        }
+        self.dimension()?;
+        Ok(self)
+    }
+
+    /// The size of one of this unit in SI base units.
+    pub fn scale(&self) -> f64 {
+        self.factors
+            .iter()
+            .map(|factor| factor.scale.powi(factor.power))
+            .product()
+    }
+
+    /// How many of `other` one of this unit is, if they measure the same
+    /// dimension: `km` is 1000 `m`.
+    pub fn conversion(&self, other: &Unit) -> Option<f64> {
+        let same = self.dimension().ok()? == other.dimension().ok()?;
+        same.then(|| self.scale() / other.scale())
+    }
+
+    /// The product of this unit and `other`, with the number that a value
+    /// has to be multiplied by. Units of `other` that measure the same as
+    /// one of this unit's are converted into it, so `km * m` is `km^2`
+    /// times 0.001, and units that cancel out are dropped. When units of two
+    /// quantities cancel out altogether, like those of `Hz * s` or
+    /// `kWh / J`, the product is a plain number.
+    pub fn multiply(&self, other: &Unit) -> Result<(Unit, f64), PowerOverflow> {
+        let mut factors = self.factors.clone();
+        let mut multiplier = 1.0;
+        for factor in &other.factors {
+            let same = factors[..self.factors.len()].iter_mut().find(|existing| {
+                existing.symbol == factor.symbol || existing.dimension == factor.dimension
+            });
+            match same {
+                Some(existing) => {
+                    multiplier *= (factor.scale / existing.scale).powi(factor.power);
+                    existing.power += factor.power;
+                }
+                None => factors.push(factor.clone()),
+            }
+        }
+        factors.retain(|factor| factor.power != 0);
+        let unit = Unit { factors }.check()?;
+        if !self.is_none() && !other.is_none() && unit.dimension()?.is_none() {
+            return Ok((Unit::default(), multiplier * unit.scale()));
+        }
+        Ok((unit, multiplier))
+    }
+
+    /// This unit raised to a whole power.
+    pub fn pow(&self, power: i32) -> Result<Unit, PowerOverflow> {
+        let mut factors = self.factors.clone();
+        for factor in &mut factors {
+            factor.power = factor.power.checked_mul(power).ok_or(PowerOverflow)?;
+        }
+        factors.retain(|factor| factor.power != 0);
+        Unit { factors }.check()
+    }
+
+    /// This unit raised to the power `exponent`, or `None` unless every
+    /// unit in it ends up with a whole power: `m^2` has a square root but
+    /// `m` does not.
+    pub fn root_or_pow(&self, exponent: f64) -> Result<Option<Unit>, PowerOverflow> {
+        let mut factors = self.factors.clone();
+        for factor in &mut factors {
+            let power = f64::from(factor.power) * exponent;
+            if (power - power.round()).abs() > 1e-9 {
+                return Ok(None);
+            }
+            if power.abs() > f64::from(i8::MAX) {
+                return Err(PowerOverflow);
+            }
+            factor.power = power.round() as i32;
+        }
+        factors.retain(|factor| factor.power != 0);
+        Unit { factors }.check().map(Some)
+    }
+}
+
+impl fmt::Display for Unit {
+    /// Prints the unit so that it reads back the same, such as `m/s^2`,
+    /// `kW*h` or `s^-1`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let factors: Vec<(String, i32)> = self
+            .factors
+            .iter()
+            .map(|factor| (factor.symbol.clone(), factor.power))
+            .collect();
+        write_factors(f, &factors)
+    }
+}
+
+/// Writes named units with powers: those with positive powers joined by
+/// `*`, then each negative one after a `/`. Without any positive powers,
+/// negative powers are written out, as in `s^-1`.
+fn write_factors(f: &mut fmt::Formatter, factors: &[(String, i32)]) -> fmt::Result {
+    let write_factor = |f: &mut fmt::Formatter, name: &str, power: i32| match power {
+        1 => write!(f, "{}", name),
+        power => write!(f, "{}^{}", name, power),
+    };
+    if factors.iter().all(|(_, power)| *power < 0) {
+        for (i, (name, power)) in factors.iter().enumerate() {
+            if i > 0 {
+                write!(f, "*")?;
+            }
+            write_factor(f, name, *power)?;
+        }
+        return Ok(());
+    }
+    let numerator = factors.iter().filter(|(_, power)| *power > 0);
+    for (i, (name, power)) in numerator.enumerate() {
+        if i > 0 {
+            write!(f, "*")?;
+        }
+        write_factor(f, name, *power)?;
+    }
+    for (name, power) in factors.iter().filter(|(_, power)| *power < 0) {
+        write!(f, "/")?;
+        write_factor(f, name, -power)?;
+    }
+    Ok(())
+}
+
+/// A number with a unit, such as `5.3 km`. The value is in `unit`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    pub value: f64,
+    pub unit: Unit,
+}
+
+impl Quantity {
+    pub fn new(value: f64, unit: Unit) -> Quantity {
+        Quantity { value, unit }
+    }
+
+    /// The same quantity in `unit`, if it measures the same dimension.
+    pub fn convert(&self, unit: &Unit) -> Option<Quantity> {
+        let factor = self.unit.conversion(unit)?;
+        Some(Quantity::new(self.value * factor, unit.clone()))
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.unit.is_none() {
+            write!(f, "{}", self.value)
+        } else {
+            write!(f, "{} {}", self.value, self.unit)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(text: &[(&str, i32)]) -> Unit {
+        let factors: Vec<(String, i32)> = text
+            .iter()
+            .map(|(name, power)| (name.to_string(), *power))
+            .collect();
+        Unit::from_factors(&factors).unwrap()
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(Unit::lookup("km").unwrap().scale(), 1e3);
+        assert_eq!(Unit::lookup("kg").unwrap().scale(), 1.0);
+        assert_eq!(Unit::lookup("kWh").unwrap().scale(), 3.6e6);
+        assert_eq!(Unit::lookup("dam").unwrap().scale(), 10.0);
+        // Units of their own are not prefixed units
+        assert_eq!(Unit::lookup("min").unwrap().scale(), 60.0);
+        assert_eq!(Unit::lookup("mi").unwrap().scale(), 1609.344);
+        assert_eq!(Unit::lookup("Pa").unwrap().dimension(), Ok(PRESSURE));
+        assert!(Unit::lookup("kft").is_none());
+        assert!(Unit::lookup("in").is_none());
+        assert!(!is_unit("x"));
+        for (i, unit) in UNITS.iter().enumerate() {
+            assert!(
+                UNITS[i + 1..]
+                    .iter()
+                    .all(|other| other.symbol != unit.symbol),
+                "{} is defined twice",
+                unit.symbol
+            );
+        }
+    }
+
+    #[test]
+    fn test_conversion() {
+        let speed = unit(&[("m", 1), ("s", -1)]);
+        assert_eq!(speed.dimension(), Ok(SPEED));
+        assert_eq!(
+            Unit::lookup("mph").unwrap().conversion(&speed),
+            Some(0.447_04)
+        );
+        let energy = unit(&[("kW", 1), ("h", 1)]);
+        assert_eq!(energy.conversion(&Unit::lookup("kWh").unwrap()), Some(1.0));
+        assert_eq!(Unit::lookup("m").unwrap().conversion(&speed), None);
+        let distance = Quantity::new(5.0, Unit::lookup("km").unwrap());
+        let metres = distance.convert(&Unit::lookup("m").unwrap()).unwrap();
+        assert_eq!(metres.to_string(), "5000 m");
+    }
+
+    #[test]
+    fn test_multiply() {
+        let km = Unit::lookup("km").unwrap();
+        let (area, multiplier) = km.multiply(&Unit::lookup("m").unwrap()).unwrap();
+        assert_eq!(area.to_string(), "km^2");
+        assert_eq!(multiplier, 1e-3);
+        let per_metre = Unit::lookup("m").unwrap().pow(-1).unwrap();
+        let (ratio, multiplier) = km.multiply(&per_metre).unwrap();
+        assert!(ratio.is_none());
+        assert_eq!(multiplier, 1e3);
+        let (energy, _) = Unit::lookup("kW")
+            .unwrap()
+            .multiply(&Unit::lookup("h").unwrap())
+            .unwrap();
+        assert_eq!(energy.to_string(), "kW*h");
+        // Reciprocal dimensions cancel, taking their scales along
+        let (count, multiplier) = Unit::lookup("kHz")
+            .unwrap()
+            .multiply(&Unit::lookup("s").unwrap())
+            .unwrap();
+        assert!(count.is_none());
+        assert_eq!(multiplier, 1e3);
+        let (ratio, multiplier) = unit(&[("kW", 1), ("h", 1)])
+            .multiply(&unit(&[("J", -1)]))
+            .unwrap();
+        assert!(ratio.is_none());
+        assert_eq!(multiplier, 3.6e6);
+        // A unit on its own keeps its name, even without a dimension
+        let (angle, _) = Unit::default()
+            .multiply(&Unit::lookup("deg").unwrap())
+            .unwrap();
+        assert_eq!(angle.to_string(), "deg");
+        assert_eq!(area.root_or_pow(0.5), Ok(Some(km.clone())));
+        assert_eq!(km.root_or_pow(0.5), Ok(None));
+        // Powers of named and base units must stay within 127
+        assert_eq!(km.root_or_pow(256.0), Err(PowerOverflow));
+        let metres = unit(&[("m", 100)]);
+        assert_eq!(metres.multiply(&unit(&[("N", 100)])), Err(PowerOverflow));
+        assert_eq!(metres.multiply(&metres), Err(PowerOverflow));
+        assert!(unit(&[("rad", 100), ("rad", 100)]).check().is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            unit(&[("kg", 1), ("m", 1), ("s", -2)]).to_string(),
+            "kg*m/s^2"
+        );
+        assert_eq!(unit(&[("s", -1)]).to_string(), "s^-1");
+        assert_eq!(unit(&[("m", 1), ("s", -1), ("s", -1)]).to_string(), "m/s^2");
+        assert_eq!(POWER.to_string(), "m^2*kg/s^3");
+        assert_eq!(Dimension::NONE.to_string(), "");
+    }
+}